@@ -0,0 +1,406 @@
+//! Reading and writing of AMF0 bodies.
+//!
+//! flash-lso writes `Value::AMF3` as a plain AMF3 value, while its reader expects the AMF3 switch
+//! marker followed by the object without its own type marker, so saved files with AMF3 values
+//! wouldn't read back. AMF3 values other than objects can't be read after the switch marker, they
+//! can't be written either. This writer produces the same bytes as flash-lso for every other value.
+//!
+//! flash-lso only reads whole AMF0 bodies, the reader here reads one element at a time and gives
+//! the same values as flash-lso.
+
+use std::convert::TryInto;
+use std::rc::Rc;
+
+use flash_lso::types::{ClassDefinition, Element, Value};
+
+use crate::amf3;
+use crate::convert::ConvertError;
+use crate::custom::CustomClasses;
+use crate::path::ValuePath;
+use crate::value;
+
+const NUMBER_MARKER: u8 = 0x00;
+const BOOLEAN_MARKER: u8 = 0x01;
+const STRING_MARKER: u8 = 0x02;
+const OBJECT_MARKER: u8 = 0x03;
+const MOVIE_CLIP_MARKER: u8 = 0x04;
+const NULL_MARKER: u8 = 0x05;
+const UNDEFINED_MARKER: u8 = 0x06;
+const REFERENCE_MARKER: u8 = 0x07;
+const MIXED_ARRAY_MARKER: u8 = 0x08;
+const OBJECT_END_MARKER: u8 = 0x09;
+const ARRAY_MARKER: u8 = 0x0a;
+const DATE_MARKER: u8 = 0x0b;
+const LONG_STRING_MARKER: u8 = 0x0c;
+const UNSUPPORTED_MARKER: u8 = 0x0d;
+const RECORD_SET_MARKER: u8 = 0x0e;
+const XML_MARKER: u8 = 0x0f;
+const TYPED_OBJECT_MARKER: u8 = 0x10;
+const AMF3_MARKER: u8 = 0x11;
+
+const PADDING: u8 = 0x00;
+
+/// Writes the elements of an AMF0 file, everything after the header. Fails on the first value
+/// that AMF0 can't hold.
+pub fn write_body(elements: &[Element], classes: &CustomClasses) -> Result<Vec<u8>, ConvertError> {
+    let mut out = Vec::new();
+
+    for element in elements {
+        write_element(&mut out, element, &ValuePath::default(), classes)?;
+        out.push(PADDING);
+    }
+
+    Ok(out)
+}
+
+fn write_element(
+    out: &mut Vec<u8>,
+    element: &Element,
+    parent: &ValuePath,
+    classes: &CustomClasses,
+) -> Result<(), ConvertError> {
+    write_string(out, &element.name);
+    write_value(out, element.value(), &parent.child(&element.name), classes)
+}
+
+fn write_string(out: &mut Vec<u8>, string: &str) {
+    out.extend_from_slice(&(string.len() as u16).to_be_bytes());
+    out.extend_from_slice(string.as_bytes());
+}
+
+fn write_long_string(out: &mut Vec<u8>, string: &str) {
+    out.extend_from_slice(&(string.len() as u32).to_be_bytes());
+    out.extend_from_slice(string.as_bytes());
+}
+
+fn write_properties(
+    out: &mut Vec<u8>,
+    elements: &[Element],
+    path: &ValuePath,
+    classes: &CustomClasses,
+) -> Result<(), ConvertError> {
+    for element in elements {
+        write_element(out, element, path, classes)?;
+    }
+
+    // An empty name followed by the end marker
+    write_string(out, "");
+    out.push(OBJECT_END_MARKER);
+    Ok(())
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value, path: &ValuePath, classes: &CustomClasses) -> Result<(), ConvertError> {
+    match value {
+        Value::Number(number) => {
+            out.push(NUMBER_MARKER);
+            out.extend_from_slice(&number.to_be_bytes());
+        }
+        Value::Bool(bool) => {
+            out.push(BOOLEAN_MARKER);
+            out.push(*bool as u8);
+        }
+        Value::String(string) if string.len() > u16::MAX as usize => {
+            out.push(LONG_STRING_MARKER);
+            write_long_string(out, string);
+        }
+        Value::String(string) => {
+            out.push(STRING_MARKER);
+            write_string(out, string);
+        }
+        Value::Object(elements, Some(class_definition)) => {
+            out.push(TYPED_OBJECT_MARKER);
+            write_string(out, &class_definition.name);
+            write_properties(out, elements, path, classes)?;
+        }
+        Value::Object(elements, None) => {
+            out.push(OBJECT_MARKER);
+            write_properties(out, elements, path, classes)?;
+        }
+        Value::Null => out.push(NULL_MARKER),
+        Value::Undefined => out.push(UNDEFINED_MARKER),
+        Value::StrictArray(values) => {
+            out.push(ARRAY_MARKER);
+            out.extend_from_slice(&(values.len() as u32).to_be_bytes());
+
+            for (index, value) in values.iter().enumerate() {
+                write_value(out, value, &path.index(index), classes)?;
+            }
+        }
+        Value::Date(millis, timezone) => {
+            out.push(DATE_MARKER);
+            out.extend_from_slice(&millis.to_be_bytes());
+            out.extend_from_slice(&timezone.unwrap_or(0).to_be_bytes());
+        }
+        Value::XML(content, _) => {
+            out.push(XML_MARKER);
+            write_long_string(out, content);
+        }
+        // AMF0 has no dense part, it's dropped like flash-lso does
+        Value::ECMAArray(_, elements, length) => {
+            out.push(MIXED_ARRAY_MARKER);
+            out.extend_from_slice(&length.to_be_bytes());
+            write_properties(out, elements, path, classes)?;
+        }
+        Value::AMF3(inner) => {
+            // The reader parses an object straight after the switch marker
+            let bytes = amf3::encode(inner, classes);
+            match bytes.split_first() {
+                Some((&amf3::OBJECT_MARKER, object)) => {
+                    out.push(AMF3_MARKER);
+                    out.extend_from_slice(object);
+                }
+                _ => return Err(unwritable(inner, path)),
+            }
+        }
+        Value::Unsupported => out.push(UNSUPPORTED_MARKER),
+        // AMF3 only values can only be stored as objects inside `Value::AMF3`
+        value => return Err(unwritable(value, path)),
+    }
+
+    Ok(())
+}
+
+fn unwritable(value: &Value, path: &ValuePath) -> ConvertError {
+    ConvertError {
+        path: path.clone(),
+        type_name: value::type_name(value),
+    }
+}
+
+/// Reads one element of an AMF0 body and the padding after it, advancing `i` past them.
+/// Returns `None` if the element is invalid or cut off, in which case `i` is left anywhere.
+pub fn read_element(i: &mut &[u8], classes: &CustomClasses) -> Option<Element> {
+    let name = read_string(i)?;
+    let value = read_value(i, classes)?;
+
+    match take(i, 1)? {
+        [PADDING] => Some(Element::new(name, value)),
+        _ => None,
+    }
+}
+
+/// Takes the next `count` bytes from `i`.
+pub fn take<'a>(i: &mut &'a [u8], count: usize) -> Option<&'a [u8]> {
+    if i.len() < count {
+        return None;
+    }
+
+    let (taken, rest) = i.split_at(count);
+    *i = rest;
+    Some(taken)
+}
+
+fn read_u8(i: &mut &[u8]) -> Option<u8> {
+    take(i, 1).map(|bytes| bytes[0])
+}
+
+fn read_u16(i: &mut &[u8]) -> Option<u16> {
+    take(i, 2)?.try_into().ok().map(u16::from_be_bytes)
+}
+
+fn read_u32(i: &mut &[u8]) -> Option<u32> {
+    take(i, 4)?.try_into().ok().map(u32::from_be_bytes)
+}
+
+fn read_f64(i: &mut &[u8]) -> Option<f64> {
+    take(i, 8)?.try_into().ok().map(f64::from_be_bytes)
+}
+
+/// Reads a string with a 16 bit length, as used for names.
+pub fn read_string(i: &mut &[u8]) -> Option<String> {
+    let length = read_u16(i)? as usize;
+    String::from_utf8(take(i, length)?.to_vec()).ok()
+}
+
+fn read_long_string(i: &mut &[u8]) -> Option<String> {
+    let length = read_u32(i)? as usize;
+    String::from_utf8(take(i, length)?.to_vec()).ok()
+}
+
+fn read_properties(i: &mut &[u8], classes: &CustomClasses) -> Option<Vec<Element>> {
+    let mut elements = Vec::new();
+
+    loop {
+        let name = read_string(i)?;
+
+        // The name before the end marker is ignored, like flash-lso does
+        if i.first() == Some(&OBJECT_END_MARKER) {
+            *i = &i[1..];
+            return Some(elements);
+        }
+
+        elements.push(Element::new(name, read_value(i, classes)?));
+    }
+}
+
+fn read_value(i: &mut &[u8], classes: &CustomClasses) -> Option<Value> {
+    let value = match read_u8(i)? {
+        NUMBER_MARKER => Value::Number(read_f64(i)?),
+        BOOLEAN_MARKER => Value::Bool(read_u8(i)? > 0),
+        STRING_MARKER => Value::String(read_string(i)?),
+        OBJECT_MARKER => Value::Object(read_properties(i, classes)?, None),
+        NULL_MARKER => Value::Null,
+        UNDEFINED_MARKER => Value::Undefined,
+        MIXED_ARRAY_MARKER => {
+            let length = read_u32(i)?;
+            Value::ECMAArray(Vec::new(), read_properties(i, classes)?, length)
+        }
+        ARRAY_MARKER => {
+            let length = read_u32(i)? as usize;
+            // Every entry takes at least one byte, don't allocate for lengths that can't be right
+            if i.len() < length {
+                return None;
+            }
+
+            let mut values = Vec::with_capacity(length);
+            for _ in 0..length {
+                values.push(Rc::new(read_value(i, classes)?));
+            }
+            Value::StrictArray(values)
+        }
+        DATE_MARKER => {
+            let millis = read_f64(i)?;
+            Value::Date(millis, Some(read_u16(i)?))
+        }
+        LONG_STRING_MARKER => Value::String(read_long_string(i)?),
+        XML_MARKER => Value::XML(read_long_string(i)?, true),
+        TYPED_OBJECT_MARKER => {
+            let name = read_string(i)?;
+            Value::Object(read_properties(i, classes)?, Some(ClassDefinition::default_with_name(name)))
+        }
+        AMF3_MARKER => {
+            // The object follows without its marker, put it back so the public decoder can read it
+            let mut bytes = Vec::with_capacity(i.len() + 1);
+            bytes.push(amf3::OBJECT_MARKER);
+            bytes.extend_from_slice(i);
+
+            let (rest, value) = classes.decoder().parse_single_element(&bytes).ok()?;
+            let consumed = bytes.len() - rest.len() - 1;
+            *i = &i[consumed..];
+            Value::AMF3(value)
+        }
+        MOVIE_CLIP_MARKER | REFERENCE_MARKER | RECORD_SET_MARKER | OBJECT_END_MARKER => return None,
+        // Unknown markers are read as unsupported values, like flash-lso does
+        _ => Value::Unsupported,
+    };
+
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flash_lso::types::{AMFVersion, Lso};
+
+    use crate::document::SolDocument;
+
+    fn write(body: Vec<Element>) -> Result<Vec<u8>, ConvertError> {
+        let lso = Lso::new(body, "test", AMFVersion::AMF0);
+        SolDocument::new(lso, None, Rc::new(CustomClasses::default())).serialize()
+    }
+
+    /// Writes `body` and reads it back with flash-lso and with `read_element`.
+    fn round_trip(body: Vec<Element>) {
+        let classes = CustomClasses::default();
+        let bytes = write(body.clone()).unwrap();
+
+        assert_eq!(classes.parse(&bytes).unwrap().body, body);
+
+        let body_bytes = write_body(&body, &classes).unwrap();
+        let mut i = &body_bytes[..];
+        let mut read = Vec::new();
+        while !i.is_empty() {
+            read.push(read_element(&mut i, &classes).unwrap());
+        }
+        assert_eq!(read, body);
+    }
+
+    fn amf3_object(elements: Vec<Element>) -> Value {
+        Value::AMF3(Rc::new(Value::Object(elements, value::anonymous_class(AMFVersion::AMF3))))
+    }
+
+    #[test]
+    fn amf0_values_round_trip() {
+        round_trip(vec![
+            Element::new("number", Value::Number(-1.5)),
+            Element::new("bool", Value::Bool(true)),
+            Element::new("string", Value::String("text".to_string())),
+            Element::new("long_string", Value::String("x".repeat(u16::MAX as usize + 1))),
+            Element::new("object", Value::Object(vec![Element::new("a", Value::Null)], None)),
+            Element::new(
+                "typed_object",
+                Value::Object(vec![Element::new("b", Value::Undefined)], Some(ClassDefinition::default_with_name("Point".to_string()))),
+            ),
+            Element::new("array", Value::StrictArray(vec![Rc::new(Value::Number(1.0)), Rc::new(Value::Bool(false))])),
+            Element::new("ecma_array", Value::ECMAArray(Vec::new(), vec![Element::new("key", Value::Number(2.0))], 1)),
+            Element::new("date", Value::Date(1_000_000.0, Some(0))),
+            Element::new("xml", Value::XML("<a/>".to_string(), true)),
+            Element::new("unsupported", Value::Unsupported),
+        ]);
+    }
+
+    #[test]
+    fn amf3_objects_round_trip() {
+        round_trip(vec![
+            Element::new(
+                "wrapped",
+                amf3_object(vec![
+                    Element::new("integer", Value::Integer(3)),
+                    Element::new("bytes", Value::ByteArray(vec![1, 2, 3])),
+                    Element::new("ints", Value::VectorInt(vec![-1, 2], false)),
+                    Element::new("uints", Value::VectorUInt(vec![1, 2], true)),
+                    Element::new("doubles", Value::VectorDouble(vec![0.5], false)),
+                    Element::new(
+                        "dictionary",
+                        Value::Dictionary(vec![(Rc::new(Value::Integer(1)), Rc::new(Value::String("one".to_string())))], false),
+                    ),
+                ]),
+            ),
+            Element::new("after", Value::Number(1.0)),
+        ]);
+    }
+
+    #[test]
+    fn other_amf3_values_are_rejected() {
+        let values = vec![
+            Value::Integer(3),
+            Value::Number(1.0),
+            Value::String("text".to_string()),
+            Value::StrictArray(Vec::new()),
+            Value::ECMAArray(Vec::new(), Vec::new(), 0),
+            Value::ByteArray(vec![1]),
+            Value::VectorInt(vec![1], false),
+            Value::VectorUInt(vec![1], false),
+            Value::VectorDouble(vec![1.0], false),
+            Value::VectorObject(Vec::new(), "Object".to_string(), false),
+            Value::Dictionary(Vec::new(), false),
+        ];
+
+        for value in values {
+            let type_name = value::type_name(&value);
+            let object = Value::Object(vec![Element::new("x", Value::AMF3(Rc::new(value)))], None);
+
+            assert_eq!(
+                write(vec![Element::new("o", object)]),
+                Err(ConvertError {
+                    path: "o.x".parse().unwrap(),
+                    type_name,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn amf3_only_values_must_be_wrapped() {
+        let array = Value::StrictArray(vec![Rc::new(Value::Number(1.0)), Rc::new(Value::Integer(2))]);
+
+        assert_eq!(
+            write(vec![Element::new("a", array)]),
+            Err(ConvertError {
+                path: "a[1]".parse().unwrap(),
+                type_name: "Integer",
+            })
+        );
+    }
+}
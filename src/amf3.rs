@@ -0,0 +1,33 @@
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Element, Lso, Value};
+use flash_lso::write;
+
+use crate::custom::CustomClasses;
+
+/// Type marker of an AMF3 object, which is also used for externalizable (`Value::Custom`) objects.
+pub const OBJECT_MARKER: u8 = 0x0a;
+
+/// Reads `bytes` as a single AMF3 value, failing if anything is left over.
+#[cfg(feature = "gui")]
+pub fn decode(bytes: &[u8], classes: &CustomClasses) -> Option<Value> {
+    match classes.decoder().parse_single_element(bytes) {
+        Ok(([], value)) => Some(value.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Writes `value` as a single AMF3 value, including its type marker.
+pub fn encode(value: &Value, classes: &CustomClasses) -> Vec<u8> {
+    // flash-lso only exposes writing whole files, so write the value as the only element of an
+    // empty-named AMF3 file and cut it out of the body, which is the element name, the value and
+    // a padding byte. Empty strings are never referenced so the value is encoded as if alone.
+    let header_length = write::write_to_bytes(&Lso::new_empty("", AMFVersion::AMF3)).len();
+    let element = Element {
+        name: String::new(),
+        value: Rc::new(value.clone()),
+    };
+    let bytes = classes.write(&Lso::new(vec![element], "", AMFVersion::AMF3));
+
+    bytes[header_length + 1..bytes.len() - 1].to_vec()
+}
@@ -1,18 +1,230 @@
-use std::fs::File;
-use std::io::{Read};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
 use eframe::{egui, epi};
-use eframe::egui::Ui;
-use flash_lso::read::Reader;
-use flash_lso::types::{AMFVersion, Element, Header, Lso, Value};
-use substring::Substring;
+use eframe::egui::{Color32, Ui};
+use flash_lso::types::{AMFVersion, Element, Lso, Value};
+use regex::Regex;
+
+use crate::byte_array::{self, NestedValue};
+use crate::custom::{self, CustomClasses};
+use crate::date::{self, DateTime};
+use crate::diff::{self, Change, Difference};
+use crate::document::{OpenError, SolDocument};
+use crate::history::{Edit, History};
+use crate::merge::{self, Merge, Side};
+use crate::path::ValuePath;
+use crate::query::{Query, QueryError};
+use crate::replace::Replace;
+use crate::retype;
+use crate::script;
+use crate::search::{Pattern, Search};
+use crate::text::Format;
+use crate::value;
+
+/// Colour of the names of rows edited since the file was opened or saved.
+const DIRTY_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+/// Colour of the badge on values switching to AMF3 encoding inside an AMF0 file.
+const AMF3_BADGE_COLOR: Color32 = Color32::from_rgb(100, 160, 255);
+/// Colours of the values only in the compared file, only in the open document, and changed.
+const ADDED_COLOR: Color32 = Color32::from_rgb(80, 200, 80);
+const REMOVED_COLOR: Color32 = Color32::from_rgb(230, 80, 80);
+const CHANGED_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+/// Colour behind the names of rows found by the search.
+const SEARCH_HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(90, 75, 20);
 
 pub enum Message {
     FileOpen(std::path::PathBuf),
+    FileSave(std::path::PathBuf),
+    /// Write the bytes of a ByteArray to a file.
+    ExportBytes(std::path::PathBuf, Vec<u8>),
+    /// Replace the ByteArray at the path with the contents of a file.
+    ImportBytes(std::path::PathBuf, ValuePath),
+    /// Write the document to a file as text.
+    Export(std::path::PathBuf, Format),
+    /// Open a document exported as text, in the format given by the file extension.
+    Import(std::path::PathBuf),
+    /// Compare the document with another file.
+    Compare(std::path::PathBuf),
+    /// The common base picked for a merge, their version is asked for next.
+    MergeBase(std::path::PathBuf),
+    /// Merge their version of the document into it, from the common base and their version.
+    Merge(std::path::PathBuf, std::path::PathBuf),
     // Other messages
 }
 
-pub struct App {
+/// A second file and how the open document differs from it.
+struct Comparison {
+    path: PathBuf,
     lso: Lso,
+    differences: Vec<Difference>,
+}
+
+/// An element whose name is being edited.
+struct Renaming {
+    parent: ValuePath,
+    index: usize,
+    name: String,
+    /// Focus the name field, set when renaming starts.
+    focus: bool,
+}
+
+/// A value whose type is being changed, with the type picked for it.
+struct Retyping {
+    path: ValuePath,
+    target: &'static str,
+}
+
+/// What is typed into the search panel.
+struct SearchPanel {
+    text: String,
+    regex: bool,
+    case_sensitive: bool,
+    in_names: bool,
+    in_values: bool,
+    /// Bounds of the numbers to find, empty for no bound.
+    min: String,
+    max: String,
+    type_name: Option<&'static str>,
+    /// The result last clicked.
+    selected: Option<ValuePath>,
+}
+
+impl Default for SearchPanel {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            regex: false,
+            case_sensitive: false,
+            in_names: true,
+            in_values: true,
+            min: String::new(),
+            max: String::new(),
+            type_name: None,
+            selected: None,
+        }
+    }
+}
+
+impl SearchPanel {
+    /// The search described by the panel, or why it is invalid.
+    fn search(&self) -> Result<Search, String> {
+        let pattern = match (self.text.is_empty(), self.regex) {
+            (true, _) => None,
+            (false, false) => Some(Pattern::substring(&self.text, self.case_sensitive)),
+            (false, true) => Some(Pattern::regex(&self.text, self.case_sensitive).map_err(|error| error.to_string())?),
+        };
+        let bound = |text: &str, name: &str| match text.trim() {
+            "" => Ok(None),
+            text => text.parse().map(Some).map_err(|_| format!("The {} {:?} is not a number", name, text)),
+        };
+
+        Ok(Search {
+            pattern,
+            in_names: self.in_names,
+            in_values: self.in_values,
+            min: bound(&self.min, "minimum")?,
+            max: bound(&self.max, "maximum")?,
+            type_name: self.type_name,
+        })
+    }
+}
+
+/// The values a bulk replace changes.
+#[derive(Clone, Copy, PartialEq)]
+enum ReplaceTarget {
+    Strings,
+    Numbers,
+}
+
+/// What is typed into the replace window.
+struct ReplacePanel {
+    target: ReplaceTarget,
+    /// The regex strings have to match, or the query selecting the numbers.
+    pattern: String,
+    /// The text replacing the matches, or the number the numbers are set to.
+    replacement: String,
+}
+
+impl ReplacePanel {
+    /// The replace described by the window, or why it is invalid.
+    fn replace(&self) -> Result<Replace, String> {
+        match self.target {
+            ReplaceTarget::Strings => Ok(Replace::Strings {
+                regex: Regex::new(&self.pattern).map_err(|error| error.to_string())?,
+                replacement: self.replacement.clone(),
+            }),
+            ReplaceTarget::Numbers => Ok(Replace::Numbers {
+                query: self.pattern.parse().map_err(|error: QueryError| error.to_string())?,
+                number: self
+                    .replacement
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{:?} is not a number", self.replacement))?,
+            }),
+        }
+    }
+}
+
+/// The script console, with what the last run printed.
+#[derive(Default)]
+struct ScriptConsole {
+    script: String,
+    output: Vec<String>,
+    error: Option<String>,
+}
+
+/// A merge of their version of the document into the open one.
+struct MergeSession {
+    base: Lso,
+    theirs: Lso,
+    theirs_path: PathBuf,
+    /// The side picked for each conflict.
+    choices: HashMap<ValuePath, Side>,
+    result: Merge,
+}
+
+pub struct App {
+    document: SolDocument,
+    /// Values edited since the document was opened or last saved.
+    dirty: HashSet<ValuePath>,
+    /// Values decoded from ByteArrays, `None` if the bytes couldn't be decoded.
+    decoded: HashMap<ValuePath, Option<NestedValue>>,
+    /// Tree nodes that are expanded, everything else is collapsed and not drawn.
+    expanded: HashSet<ValuePath>,
+    /// Expand every node drawn in the next frame.
+    expand_all: bool,
+    /// The last failure, shown until it is dismissed.
+    error: Option<String>,
+    /// A file that failed to open because of corrupted data, which can be partially recovered.
+    recoverable: Option<PathBuf>,
+    /// The file the document is compared with, shown in the diff window.
+    comparison: Option<Comparison>,
+    /// The merge shown in the merge window.
+    merge: Option<MergeSession>,
+    /// Edits made since the document was opened, for undo and redo.
+    history: History,
+    /// Show the history panel.
+    history_open: bool,
+    renaming: Option<Renaming>,
+    /// The element being dragged to another place, by its parent and index.
+    dragging: Option<(ValuePath, usize)>,
+    /// The value shown in the change type window.
+    retyping: Option<Retyping>,
+    /// The bulk replace shown in the replace window.
+    replacing: Option<ReplacePanel>,
+    /// The query in the query bar, its matches are listed below it.
+    query: String,
+    /// Show the search panel.
+    search_open: bool,
+    search: SearchPanel,
+    /// The row to scroll to when it is next drawn.
+    scroll_to: Option<ValuePath>,
+    /// Show the script console.
+    console_open: bool,
+    console: ScriptConsole,
 
     message_channel: (
         std::sync::mpsc::Sender<Message>,
@@ -23,11 +235,27 @@ pub struct App {
 impl Default for App {
     fn default() -> Self {
         Self {
-            lso: Lso { header: Header {
-                length: 0,
-                name: "Not Loaded".to_string(),
-                format_version: AMFVersion::AMF0
-            }, body: vec![] },
+            document: SolDocument::default(),
+            dirty: HashSet::new(),
+            decoded: HashMap::new(),
+            expanded: HashSet::new(),
+            expand_all: false,
+            error: None,
+            recoverable: None,
+            comparison: None,
+            merge: None,
+            history: History::default(),
+            history_open: false,
+            renaming: None,
+            dragging: None,
+            retyping: None,
+            replacing: None,
+            query: String::new(),
+            search_open: false,
+            search: SearchPanel::default(),
+            scroll_to: None,
+            console_open: false,
+            console: ScriptConsole::default(),
             message_channel: std::sync::mpsc::channel(),
         }
     }
@@ -41,19 +269,107 @@ impl epi::App for App {
         // and messages are not processed
         ctx.request_repaint();
 
+        let time = ctx.input().time;
+
         loop {
             match self.message_channel.1.try_recv() {
-                Ok(message) => {
-                    let Message::FileOpen(path_buf) = message;
-                    let mut file = File::open(path_buf).unwrap();
-                    let mut data = Vec::new();
+                Ok(Message::FileOpen(path_buf)) => {
+                    // The previous document stays open if this one can't be read
+                    match SolDocument::open(path_buf.clone(), Rc::clone(&self.document.classes)) {
+                        Ok(document) => {
+                            self.set_document(document);
+                            self.error = None;
+                        }
+                        Err(error) => {
+                            self.report_error(format!("Failed to open {}: {}", path_buf.display(), error));
+
+                            if !matches!(error, OpenError::Io(_)) {
+                                self.recoverable = Some(path_buf);
+                            }
+                        }
+                    }
+                }
+                Ok(Message::FileSave(path_buf)) => {
+                    match self.document.save_as(path_buf) {
+                        Ok(()) => self.dirty.clear(),
+                        Err(error) => self.report_error(format!("Failed to save SOL file: {}", error)),
+                    }
+                }
+                Ok(Message::ExportBytes(path_buf, bytes)) => {
+                    if let Err(error) = std::fs::write(path_buf, bytes) {
+                        self.report_error(format!("Failed to export ByteArray: {}", error));
+                    }
+                }
+                Ok(Message::ImportBytes(path_buf, path)) => match std::fs::read(path_buf) {
+                    Ok(bytes) => {
+                        if let Some(old) = path.get(&self.document.lso.body) {
+                            let edit = Edit::set_value(&path, old, &Value::ByteArray(bytes));
+                            self.apply_edit(edit, time);
+                        }
+                    }
+                    Err(error) => self.report_error(format!("Failed to import ByteArray: {}", error)),
+                },
+                Ok(Message::Export(path_buf, format)) => {
+                    if let Err(error) = std::fs::write(path_buf, format.write(&self.document.lso)) {
+                        self.report_error(format!("Failed to export {}: {}", format.name(), error));
+                    }
+                }
+                Ok(Message::Import(path_buf)) => {
+                    let result = Format::from_path(&path_buf)
+                        .ok_or_else(|| "unknown file extension".to_string())
+                        .and_then(|format| {
+                            let text = std::fs::read_to_string(&path_buf).map_err(|error| error.to_string())?;
+                            format.read(&text).map_err(|error| error.to_string())
+                        })
+                        .and_then(|lso| {
+                            // Not saved as a SOL file yet, Save asks where to write it
+                            let mut document = SolDocument::new(lso, None, Rc::clone(&self.document.classes));
+                            document.serialize().map_err(|error| error.to_string())?;
+                            Ok(document)
+                        });
 
-                    let _bytes = file.read_to_end(&mut data);
-                    let reader = Reader::default().parse(&data).unwrap();
-                    self.lso = reader.1;
+                    match result {
+                        Ok(document) => self.set_document(document),
+                        Err(error) => {
+                            self.report_error(format!("Failed to import {}: {}", path_buf.display(), error))
+                        }
+                    }
+                }
+                Ok(Message::Compare(path_buf)) => {
+                    match SolDocument::open(path_buf.clone(), Rc::clone(&self.document.classes)) {
+                        Ok(other) => {
+                            self.comparison = Some(Comparison {
+                                differences: diff::diff(&self.document.lso.body, &other.lso.body),
+                                path: path_buf,
+                                lso: other.lso,
+                            });
+                        }
+                        Err(error) => {
+                            self.report_error(format!("Failed to open {}: {}", path_buf.display(), error))
+                        }
+                    }
+                }
+                Ok(Message::MergeBase(base_path)) => pick_theirs(self.message_channel.0.clone(), base_path),
+                Ok(Message::Merge(base_path, theirs_path)) => {
+                    let classes = Rc::clone(&self.document.classes);
+                    let open = |path: &PathBuf| {
+                        SolDocument::open(path.clone(), Rc::clone(&classes))
+                            .map_err(|error| format!("Failed to open {}: {}", path.display(), error))
+                    };
 
-                    println!("{:?}", self.lso.header);
-                    println!("{:?}", self.lso.body)
+                    match open(&base_path).and_then(|base| Ok((base, open(&theirs_path)?))) {
+                        Ok((base, theirs)) => {
+                            let choices = HashMap::new();
+                            self.merge = Some(MergeSession {
+                                result: merge::merge(&base.lso, &self.document.lso, &theirs.lso, &choices),
+                                base: base.lso,
+                                theirs: theirs.lso,
+                                theirs_path,
+                                choices,
+                            });
+                        }
+                        Err(error) => self.report_error(error),
+                    }
                 }
                 Err(_) => {
                     break;
@@ -87,22 +403,144 @@ impl epi::App for App {
                             }
                         });
                     }
+                    let loaded = self.document.lso.header.length != 0;
+
+                    if ui.add_enabled(loaded, egui::Button::new("Save")).clicked() {
+                        if self.document.path.is_some() {
+                            match self.document.save() {
+                                Ok(()) => self.dirty.clear(),
+                                Err(error) => self.report_error(format!("Failed to save SOL file: {}", error)),
+                            }
+                        } else {
+                            self.save_as();
+                        }
+                    }
+                    if ui.add_enabled(loaded, egui::Button::new("Save As...")).clicked() {
+                        self.save_as();
+                    }
+                    if ui.button("Import...").clicked() {
+                        import_text(self.message_channel.0.clone());
+                    }
+                    for format in Format::ALL {
+                        let button = egui::Button::new(format!("Export {}...", format.name()));
+                        if ui.add_enabled(loaded, button).clicked() {
+                            export_text(self.message_channel.0.clone(), &self.document.lso.header.name, format);
+                        }
+                    }
+                    if ui.add_enabled(loaded, egui::Button::new("Compare with...")).clicked() {
+                        compare_with(self.message_channel.0.clone());
+                    }
+                    if ui.add_enabled(loaded, egui::Button::new("Merge...")).clicked() {
+                        pick_merge_base(self.message_channel.0.clone());
+                    }
                     if ui.button("Exit").clicked() {
                         frame.quit();
                     }
                 });
+                egui::menu::menu(ui, "Edit", |ui| {
+                    if ui
+                        .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                        .on_hover_text("Ctrl+Z")
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                    if ui
+                        .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                        .on_hover_text("Ctrl+Shift+Z")
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                    let loaded = self.document.lso.header.length != 0;
+                    if ui.add_enabled(loaded, egui::Button::new("Replace...")).clicked() && self.replacing.is_none() {
+                        self.replacing = Some(ReplacePanel {
+                            target: ReplaceTarget::Strings,
+                            pattern: String::new(),
+                            replacement: String::new(),
+                        });
+                    }
+                    ui.checkbox(&mut self.history_open, "History");
+                    ui.checkbox(&mut self.search_open, "Search").on_hover_text("Ctrl+F");
+                    ui.checkbox(&mut self.console_open, "Script console");
+                });
             });
         });
 
+        if let Some(error) = &self.error {
+            let mut dismissed = false;
+            let mut recover = false;
+
+            egui::TopBottomPanel::bottom("error_panel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::Label::new(error).text_color(Color32::RED));
+
+                    if self.recoverable.is_some() {
+                        recover = ui
+                            .button("Recover readable elements")
+                            .on_hover_text("Open the elements before the corrupted data as a new file")
+                            .clicked();
+                    }
+                    dismissed = ui.button("Dismiss").clicked();
+                });
+            });
+
+            if recover {
+                if let Some(path) = self.recoverable.take() {
+                    self.recover(&path);
+                }
+            } else if dismissed {
+                self.error = None;
+                self.recoverable = None;
+            }
+        }
+
+        if ctx.input().modifiers.command && ctx.input().key_pressed(egui::Key::F) {
+            self.search_open = true;
+        }
+
+        // Text fields handle their own undo
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = {
+                let input = ctx.input();
+                let z = input.modifiers.command && input.key_pressed(egui::Key::Z);
+                (z && !input.modifiers.shift, z && input.modifiers.shift)
+            };
+
+            if undo {
+                self.undo();
+            } else if redo {
+                self.redo();
+            }
+        }
+
+        self.show_comparison(ctx);
+        self.show_merge(ctx, time);
+        self.show_retype(ctx, time);
+        self.show_replace(ctx, time);
+        self.show_history(ctx);
+        let hits = self.show_search(ctx);
+        self.show_console(ctx, time);
+
+        let mut edits = Vec::new();
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            let header = &mut self.lso.header;
+            let header = &self.document.lso.header;
+            let version = header.format_version;
             if header.length != 0 {
                 // The central panel the region left after adding TopPanel's and SidePanel's
                 ui.heading("Header");
 
                 ui.horizontal(|ui| {
                     ui.label("Name:");
-                    ui.text_edit_singleline(&mut header.name);
+
+                    let mut name = header.name.clone();
+                    if ui.text_edit_singleline(&mut name).changed() {
+                        edits.push(Edit::Header {
+                            old: header.name.clone(),
+                            new: name,
+                        });
+                    }
                 });
 
                 ui.horizontal(|ui| {
@@ -116,20 +554,78 @@ impl epi::App for App {
                 });
 
 
-                ui.heading("Body");
-                let amf0 = header.format_version == AMFVersion::AMF0;
-                let body = &self.lso.body;
+                ui.horizontal(|ui| {
+                    ui.heading("Body");
+
+                    if ui.button("Expand all").clicked() {
+                        self.expand_all = true;
+                    }
+                    if ui.button("Collapse all").clicked() {
+                        self.expanded.clear();
+                    }
+                });
 
-                for element in body {
-                    process_element(ui, amf0, element)
+                ui.horizontal(|ui| {
+                    ui.label("Query:");
+                    ui.add(egui::TextEdit::singleline(&mut self.query).hint_text("..items[?(@.level > 10)]"));
+                });
+                if !self.query.trim().is_empty() {
+                    show_query_matches(ui, &self.query, &self.document.lso.body, &mut self.expanded);
                 }
 
+                let body = &mut self.document.lso.body;
+                let history = &mut self.history;
+                let mut context = BodyContext {
+                    dirty: &mut self.dirty,
+                    decoded: &mut self.decoded,
+                    expanded: &mut self.expanded,
+                    expand_all: self.expand_all,
+                    message_sender: &self.message_channel.0,
+                    classes: &self.document.classes,
+                    edits: &mut edits,
+                    unreachable: false,
+                    version,
+                    renaming: &mut self.renaming,
+                    dragging: &mut self.dragging,
+                    retyping: &mut self.retyping,
+                    hits: &hits,
+                    scroll_to: &mut self.scroll_to,
+                };
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let old: Vec<Rc<Value>> = body.iter().map(|element| Rc::clone(&element.value)).collect();
+
+                    // Values are edited in place, the history only needs to know what changed
+                    if process_elements(ui, &mut context, body, &ValuePath::default(), "body") {
+                        for (old, element) in old.iter().zip(body.iter()) {
+                            if !Rc::ptr_eq(old, &element.value) {
+                                let path = ValuePath::default().child(&element.name);
+                                history.record(Edit::set_value(&path, old, &element.value), time);
+                            }
+                        }
+                    }
+                });
+                self.expand_all = false;
+
                 egui::warn_if_debug_build(ui);
             } else {
                 ui.heading("No SOL file loaded.");
             }
         });
 
+        for edit in edits {
+            self.apply_edit(edit, time);
+        }
+
+        // Drops land on a row while it is drawn, releasing anywhere else cancels the drag
+        if self.dragging.is_some() {
+            if ctx.input().pointer.any_released() {
+                self.dragging = None;
+            } else {
+                ctx.output().cursor_icon = egui::CursorIcon::Grabbing;
+            }
+        }
+
         /*if false {
             egui::Window::new("Window").show(ctx, |ui| {
                 ui.label("Windows can be moved by dragging them.");
@@ -167,119 +663,1828 @@ impl epi::App for App {
     }
 }
 
-fn execute<F: std::future::Future<Output = ()> + Send + 'static>(f: F) {
-    std::thread::spawn(move || {
-        futures::executor::block_on(f);
-    });
-}
+impl App {
+    /// Logs `error` and shows it in the error panel.
+    fn report_error(&mut self, error: String) {
+        eprintln!("{}", error);
+        self.error = Some(error);
+        self.recoverable = None;
+    }
 
-fn process_element(ui: &mut Ui, amf0: bool, element: &Element) {
-    let mut no_value = false;
+    fn set_document(&mut self, document: SolDocument) {
+        self.document = document;
+        self.dirty.clear();
+        self.decoded.clear();
+        self.expanded.clear();
+        self.comparison = None;
+        self.merge = None;
+        self.history = History::default();
+        self.renaming = None;
+        self.dragging = None;
+        self.retyping = None;
+        self.replacing = None;
+    }
 
-    ui.label(&element.name);
+    /// Makes `edit` to the document and adds it to the history.
+    fn apply_edit(&mut self, edit: Edit, time: f64) {
+        if edit.apply(&mut self.document.lso) {
+            self.mark_edited(&edit);
+            self.history.record(edit, time);
+        }
+    }
 
-    let value = &element.value();
+    /// Marks the values changed by `edit` as edited. ByteArrays below them may have been replaced,
+    /// so the values decoded from them are dropped.
+    fn mark_edited(&mut self, edit: &Edit) {
+        match edit.paths() {
+            Some(paths) => {
+                for path in paths {
+                    self.decoded.retain(|decoded, _| !decoded.starts_with(&path));
+                    self.dirty.insert(path);
+                }
+            }
+            None => self.decoded.clear(),
+        }
+    }
 
-    match value {
-        Value::Number(number) => {ui.code(number)},
-        Value::Bool(bool) => {ui.code(bool)},
-        Value::String(string) => {ui.code(string)},
-        Value::Object(list_of_elements, class_definition) => {
-            for i in 0..list_of_elements.len() {
-                ui.horizontal(|ui| {
-                    ui.add_space(10.0);
+    fn undo(&mut self) {
+        if let Some(edit) = self.history.undo(&mut self.document.lso) {
+            self.mark_edited(&edit);
+        }
+    }
 
-                    let element1 = &list_of_elements[i];
+    fn redo(&mut self) {
+        if let Some(edit) = self.history.redo(&mut self.document.lso) {
+            self.mark_edited(&edit);
+        }
+    }
 
-                    ui.label(&element1.name);
-                    process_element(ui, amf0, &element1);
-                });
-            }
+    /// Draws the history panel while it is open, clicking an edit goes back or forward to it.
+    fn show_history(&mut self, ctx: &egui::CtxRef) {
+        if !self.history_open {
+            return;
+        }
 
-            if class_definition.is_some() {
-                let class_definition = &class_definition.as_ref().unwrap();
+        let position = self.history.position();
+        let mut target = None;
 
-                ui.horizontal(|ui| {
-                    ui.add_space(10.0);
-                    if class_definition.name.is_empty() {
-                        ui.label("Class Definition: \"\"");
-                    } else {
-                        ui.label("Class Definition: ");
+        egui::SidePanel::right("history_panel").show(ctx, |ui| {
+            ui.heading("History");
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if ui.selectable_label(position == 0, "Opened document").clicked() {
+                    target = Some(0);
+                }
+                for (index, edit) in self.history.edits().enumerate() {
+                    let label = ui.selectable_label(position == index + 1, edit.description());
+                    // Undone edits stay listed until a new edit replaces them
+                    if label.on_hover_text(if index < position { "Made" } else { "Undone" }).clicked() {
+                        target = Some(index + 1);
                     }
+                }
+            });
+        });
+
+        if let Some(target) = target {
+            for edit in self.history.jump(target, &mut self.document.lso) {
+                self.mark_edited(&edit);
+            }
+        }
+    }
+
+    /// Draws the search panel while it is open and returns the paths of the values found, which
+    /// are highlighted in the tree. Clicking a result expands the tree down to it and scrolls to it.
+    fn show_search(&mut self, ctx: &egui::CtxRef) -> HashSet<ValuePath> {
+        if !self.search_open {
+            return HashSet::new();
+        }
+
+        let panel = &mut self.search;
+        let body = &self.document.lso.body;
+        let mut hits = Vec::new();
+        let mut selected = None;
+
+        egui::SidePanel::left("search_panel").show(ctx, |ui| {
+            ui.heading("Search");
+
+            ui.add(egui::TextEdit::singleline(&mut panel.text).hint_text("Name or value"));
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut panel.regex, "Regex");
+                ui.checkbox(&mut panel.case_sensitive, "Match case");
+            });
+            ui.horizontal(|ui| {
+                ui.label("In:");
+                ui.checkbox(&mut panel.in_names, "Names");
+                ui.checkbox(&mut panel.in_values, "Values");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Type:");
+                egui::ComboBox::from_id_source("search_type")
+                    .selected_text(panel.type_name.unwrap_or("Any"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut panel.type_name, None, "Any");
+                        for type_name in value::NEW_TYPES.iter().copied().chain(Some("Custom")) {
+                            ui.selectable_value(&mut panel.type_name, Some(type_name), type_name);
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Numbers from");
+                ui.add(egui::TextEdit::singleline(&mut panel.min).desired_width(60.0));
+                ui.label("to");
+                ui.add(egui::TextEdit::singleline(&mut panel.max).desired_width(60.0));
+            });
+            ui.separator();
+
+            let search = match panel.search() {
+                Ok(search) => search,
+                Err(error) => {
+                    ui.add(egui::Label::new(error).text_color(Color32::RED));
+                    return;
+                }
+            };
+            if search.is_empty() {
+                return;
+            }
+            hits = search.run(body);
 
-                    ui.code(&class_definition.name);
+            ui.label(format!("{} found", hits.len()));
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("search_hits").striped(true).show(ui, |ui| {
+                    for hit in &hits {
+                        let label = ui.selectable_label(panel.selected.as_ref() == Some(&hit.path), hit.path.to_string());
+                        let hover = if hit.in_name { "Found in the name" } else { "Found in the value" };
+                        if label.on_hover_text(hover).clicked() {
+                            selected = Some(hit.path.clone());
+                        }
+                        ui.label(value::describe(&hit.value));
+                        ui.end_row();
+                    }
                 });
+            });
+        });
+
+        if let Some(path) = selected {
+            self.expanded.extend(path.prefixes());
+            self.scroll_to = Some(path.clone());
+            self.search.selected = Some(path);
+        }
+
+        hits.into_iter().map(|hit| hit.path).collect()
+    }
+
+    /// Draws the script console while it is open. Running the script applies what it changed as
+    /// a single edit.
+    fn show_console(&mut self, ctx: &egui::CtxRef, time: f64) {
+        if !self.console_open {
+            return;
+        }
 
-                let static_properties = &class_definition.static_properties;
-                let mut static_properties_string = "[".to_owned();
+        let console = &mut self.console;
+        let mut run = false;
 
-                for static_property in static_properties {
-                    static_properties_string.push_str("\"");
-                    static_properties_string.push_str(static_property);
-                    static_properties_string.push_str("\", ");
+        egui::TopBottomPanel::bottom("script_panel").resizable(true).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Script");
+                run = ui.button("▶ Run").clicked();
+                if ui.button("Clear output").clicked() {
+                    console.output.clear();
+                    console.error = None;
                 }
+            });
 
-                let static_properties_string_length = static_properties_string.len();
+            let editor = egui::TextEdit::multiline(&mut console.script)
+                .code_editor()
+                .desired_rows(6)
+                .desired_width(f32::INFINITY)
+                .hint_text("for path in children(\"\") { print(path + \": \" + kind(path)); }");
+            ui.add(editor);
 
-                if static_properties_string_length != 1 {
-                    static_properties_string = static_properties_string.substring(0, static_properties_string_length - 2).to_string();
+            egui::ScrollArea::vertical().id_source("script_output").max_height(120.0).show(ui, |ui| {
+                for line in &console.output {
+                    ui.monospace(line);
+                }
+                if let Some(error) = &console.error {
+                    ui.add(egui::Label::new(error).text_color(Color32::RED));
                 }
+            });
+        });
 
-                static_properties_string.push_str("]");
+        if !run {
+            return;
+        }
 
-                ui.horizontal(|ui| {
-                    ui.add_space(20.0);
-                    ui.label("Static Properties: ");
-                    ui.code(static_properties_string);
-                }).response
-            } else {
-                ui.code("No Class Definition Found!")
+        let lso = &self.document.lso;
+        let run = script::run(&self.console.script, &lso.body, lso.header.format_version);
+        self.console.output = run.output;
+        self.console.error = None;
+
+        match run.result {
+            Ok(body) if !diff::same_elements(&body, &lso.body) => {
+                // Everything the script changed shows up as edited until the document is saved
+                for difference in diff::diff(&lso.body, &body) {
+                    self.dirty.insert(difference.path);
+                }
+
+                let edit = Edit::Body {
+                    old: lso.body.clone(),
+                    new: body,
+                };
+                self.apply_edit(edit, time);
             }
-        },
-        Value::Null => {
-            ui.horizontal(|ui| {
-                ui.code("null");
-            }).response
-        },
-        Value::Undefined => {ui.code("undefined")},
-        //Value::ECMAArray(Vec<Rc<Value>>, Vec<Element>, u32),
-        //Value::StrictArray(Vec<Rc<Value>>),
-        //Value::Date(f64, Option<u16>)
-        Value::Unsupported => {ui.code("unsupported")},
-        Value::XML(string, bool) => {
+            Ok(_) => {}
+            Err(error) => self.console.error = Some(error),
+        }
+    }
+
+    /// Draws the diff window while a comparison is open.
+    fn show_comparison(&mut self, ctx: &egui::CtxRef) {
+        let comparison = match &mut self.comparison {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        let body = &self.document.lso.body;
+        let mut open = true;
+
+        egui::Window::new("Diff").open(&mut open).default_width(600.0).show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.code(string);
-                ui.code(bool);
-            }).response
+                ui.label(format!(
+                    "{} differences with {}",
+                    comparison.differences.len(),
+                    comparison.path.display()
+                ));
+
+                // Edits made since the file was compared only show up after a refresh
+                if ui.button("Refresh").clicked() {
+                    comparison.differences = diff::diff(body, &comparison.lso.body);
+                }
+            });
+
+            let file_name = comparison.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("diff").striped(true).show(ui, |ui| {
+                    ui.add(egui::Label::new("Path").strong());
+                    ui.add(egui::Label::new("Open document").strong());
+                    ui.add(egui::Label::new(file_name).strong());
+                    ui.end_row();
+
+                    for difference in &comparison.differences {
+                        let (color, old, new) = match &difference.change {
+                            Change::Added(value) => (ADDED_COLOR, None, Some(value)),
+                            Change::Removed(value) => (REMOVED_COLOR, Some(value), None),
+                            Change::Changed { old, new } => (CHANGED_COLOR, Some(old), Some(new)),
+                        };
+                        let describe = |value: Option<&Value>| value.map_or_else(|| "—".to_string(), value::describe);
+
+                        ui.add(egui::Label::new(difference.path.to_string()).text_color(color));
+                        ui.label(describe(old));
+                        ui.label(describe(new));
+                        if difference.change.is_type_change() {
+                            ui.add(egui::Label::new("type changed").text_color(color));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+
+        if !open {
+            self.comparison = None;
         }
-        //Value::AMF3(Rc<Value>),
-        _ => {
-            no_value = true;
-            ui.code("Couldn't find type.")
-        },
-    };
+    }
 
-    if amf0 && no_value {
-        return;
-    };
+    /// Draws the merge window while a merge is open, with the conflicts and the side picked for each.
+    fn show_merge(&mut self, ctx: &egui::CtxRef, time: f64) {
+        let session = match &mut self.merge {
+            Some(session) => session,
+            None => return,
+        };
+        let ours = &self.document.lso;
+        let mut open = true;
+        let mut apply = false;
 
-    let mut no_value2 = false;
+        egui::Window::new("Merge").open(&mut open).default_width(700.0).show(ctx, |ui| {
+            ui.label(format!(
+                "Merging {} into the open document, {} conflicts.",
+                session.theirs_path.display(),
+                session.result.conflicts.len()
+            ));
+            ui.label("Changes made on one side only are already merged. Pick the side to keep for each conflict.");
 
-    match value {
-        Value::Integer(integer) => {ui.code(integer);}
-        //Value::ByteArray(Vec<u8>) => ,
-        //Value::VectorInt(_, _) => ,
-        //Value::VectorUInt(_, _) => ,
-        //Value::VectorDouble(_, _) => ,
-        //Value::VectorObject(_, _, _) => ,
-        //Value::Dictionary(_, _) => ,
-        //Value::Custom(_, _, _) => ,
-        _ => {
-            no_value2 = true;
+            let mut changed = false;
+
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                egui::Grid::new("merge").striped(true).show(ui, |ui| {
+                    ui.add(egui::Label::new("Path").strong());
+                    ui.add(egui::Label::new("Base").strong());
+                    ui.add(egui::Label::new("Ours").strong());
+                    ui.add(egui::Label::new("Theirs").strong());
+                    ui.end_row();
+
+                    for conflict in &session.result.conflicts {
+                        let mut choice = session.choices.get(&conflict.path).copied().unwrap_or(Side::Ours);
+
+                        ui.add(egui::Label::new(conflict.path.to_string()).text_color(CHANGED_COLOR));
+                        for side in [Side::Base, Side::Ours, Side::Theirs] {
+                            let text = conflict.side(side).map_or_else(|| "(removed)".to_string(), value::describe);
+                            changed |= ui.radio_value(&mut choice, side, text).changed();
+                        }
+                        ui.end_row();
+
+                        session.choices.insert(conflict.path.clone(), choice);
+                    }
+                });
+            });
+
+            if changed {
+                session.result = merge::merge(&session.base, ours, &session.theirs, &session.choices);
+            }
+
+            apply = ui.button("Apply merge").clicked();
+        });
+
+        if apply {
+            // The document may have been edited while the window was open
+            let result = merge::merge(&session.base, ours, &session.theirs, &session.choices);
+            let merged = &result.lso;
+            // Everything the merge changed shows up as edited until the document is saved
+            for difference in diff::diff(&ours.body, &merged.body) {
+                self.dirty.insert(difference.path);
+            }
+
+            let header = (merged.header.name != ours.header.name).then(|| Edit::Header {
+                old: ours.header.name.clone(),
+                new: merged.header.name.clone(),
+            });
+            let body = Edit::Body {
+                old: ours.body.clone(),
+                new: merged.body.clone(),
+            };
+
+            for edit in header.into_iter().chain(Some(body)) {
+                self.apply_edit(edit, time);
+            }
+        }
+        if apply || !open {
+            self.merge = None;
         }
     }
 
-    if !amf0 && no_value && no_value2 {
-        ui.code("Couldn't find type.");
-    };
-}
\ No newline at end of file
+    /// Draws the change type window while a value is picked, with a preview of the converted value
+    /// and what the conversion loses.
+    fn show_retype(&mut self, ctx: &egui::CtxRef, time: f64) {
+        let retyping = match &mut self.retyping {
+            Some(retyping) => retyping,
+            None => return,
+        };
+        // The value may have been removed or changed since the window was opened
+        let value = match retyping.path.get(&self.document.lso.body) {
+            Some(value) => value,
+            None => {
+                self.retyping = None;
+                return;
+            }
+        };
+        let version = self.document.version_at(&retyping.path);
+        let targets = retype::targets(value, version);
+        if !targets.contains(&retyping.target) {
+            match targets.first() {
+                Some(target) => retyping.target = target,
+                None => {
+                    self.retyping = None;
+                    return;
+                }
+            }
+        }
+
+        let result = retype::retype(value, retyping.target, version);
+        let mut open = true;
+        let mut apply = false;
+
+        egui::Window::new("Change type").open(&mut open).show(ctx, |ui| {
+            egui::Grid::new("retype").show(ui, |ui| {
+                ui.label("Value:");
+                ui.code(retyping.path.to_string());
+                ui.end_row();
+
+                ui.label("Now:");
+                ui.label(value::describe(value));
+                ui.end_row();
+
+                ui.label("New type:");
+                egui::ComboBox::from_id_source("retype_target")
+                    .selected_text(retyping.target)
+                    .show_ui(ui, |ui| {
+                        for target in targets {
+                            ui.selectable_value(&mut retyping.target, target, target);
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Becomes:");
+                match &result {
+                    Ok(retyped) => ui.label(value::describe(&retyped.value)),
+                    Err(error) => ui.add(egui::Label::new(error).text_color(Color32::RED)),
+                };
+                ui.end_row();
+            });
+
+            if let Ok(retyped) = &result {
+                for warning in &retyped.warnings {
+                    ui.add(egui::Label::new(format!("⚠ {}", warning)).text_color(CHANGED_COLOR));
+                }
+            }
+
+            apply = ui.add_enabled(result.is_ok(), egui::Button::new("Apply")).clicked();
+        });
+
+        if let (true, Ok(retyped)) = (apply, result) {
+            // Not narrowed, the new value is a whole new value of another type
+            let edit = Edit::SetValue {
+                path: retyping.path.clone(),
+                old: value.clone(),
+                new: retyped.value,
+            };
+            self.apply_edit(edit, time);
+        }
+        if apply || !open {
+            self.retyping = None;
+        }
+    }
+
+    /// Draws the replace window while it is open, with a preview of every value that changes.
+    /// Replacing them is a single edit.
+    fn show_replace(&mut self, ctx: &egui::CtxRef, time: f64) {
+        let panel = match &mut self.replacing {
+            Some(panel) => panel,
+            None => return,
+        };
+        let body = &self.document.lso.body;
+        let mut replacements = Vec::new();
+        let mut open = true;
+        let mut apply = false;
+
+        egui::Window::new("Replace").open(&mut open).default_width(600.0).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut panel.target, ReplaceTarget::Strings, "Strings matching a regex");
+                ui.radio_value(&mut panel.target, ReplaceTarget::Numbers, "Numbers selected by a query");
+            });
+
+            let (pattern_label, pattern_hint, replacement_label, replacement_hint) = match panel.target {
+                ReplaceTarget::Strings => ("Regex:", "item_(\\d+)", "Replace with:", "weapon_$1"),
+                ReplaceTarget::Numbers => ("Query:", "..cooldown", "Set to:", "0"),
+            };
+            egui::Grid::new("replace_form").show(ui, |ui| {
+                ui.label(pattern_label);
+                ui.add(egui::TextEdit::singleline(&mut panel.pattern).hint_text(pattern_hint));
+                ui.end_row();
+
+                ui.label(replacement_label);
+                ui.add(egui::TextEdit::singleline(&mut panel.replacement).hint_text(replacement_hint));
+                ui.end_row();
+            });
+
+            if panel.pattern.trim().is_empty() {
+                return;
+            }
+            match panel.replace() {
+                Ok(replace) => replacements = replace.preview(body),
+                Err(error) => {
+                    ui.add(egui::Label::new(error).text_color(Color32::RED));
+                    return;
+                }
+            }
+
+            ui.label(format!("{} values change", replacements.len()));
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                egui::Grid::new("replace_preview").striped(true).show(ui, |ui| {
+                    ui.add(egui::Label::new("Path").strong());
+                    ui.add(egui::Label::new("Old").strong());
+                    ui.add(egui::Label::new("New").strong());
+                    ui.end_row();
+
+                    for replacement in &replacements {
+                        ui.label(replacement.path.to_string());
+                        ui.label(value::describe(&replacement.old));
+                        ui.add(egui::Label::new(value::describe(&replacement.new)).text_color(CHANGED_COLOR));
+                        ui.end_row();
+                    }
+                });
+            });
+
+            apply = ui.add_enabled(!replacements.is_empty(), egui::Button::new("Replace all")).clicked();
+        });
+
+        if apply {
+            let edits = replacements
+                .into_iter()
+                .map(|replacement| Edit::SetValue {
+                    path: replacement.path,
+                    old: replacement.old,
+                    new: replacement.new,
+                })
+                .collect();
+            self.apply_edit(Edit::Group(edits), time);
+        }
+        if apply || !open {
+            self.replacing = None;
+        }
+    }
+
+    /// Opens the readable elements of the corrupted file at `path` as a new document.
+    fn recover(&mut self, path: &Path) {
+        match SolDocument::recover(path, Rc::clone(&self.document.classes)) {
+            Ok((document, unread)) => {
+                let count = document.lso.body.len();
+                self.set_document(document);
+
+                // Not an error, but it needs to stay visible until it has been read
+                self.report_error(format!(
+                    "Recovered {} elements from {}, bytes {}..{} couldn't be read and were dropped. \
+                     Use Save As to write the recovered elements to a new file.",
+                    count,
+                    path.display(),
+                    unread.start,
+                    unread.end
+                ));
+            }
+            Err(error) => self.report_error(format!("Failed to recover {}: {}", path.display(), error)),
+        }
+    }
+
+    /// Asks for a destination file and saves the document there once one is picked.
+    fn save_as(&self) {
+        let (directory, file_name) = match &self.document.path {
+            Some(path) => (
+                path.parent().map(|parent| parent.to_path_buf()).unwrap_or_default(),
+                path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            ),
+            None => (
+                std::env::current_dir().unwrap(),
+                format!("{}.sol", self.document.lso.header.name),
+            ),
+        };
+
+        let task = rfd::AsyncFileDialog::new()
+            .add_filter("SOL files", &["sol"])
+            .set_directory(directory)
+            .set_file_name(&file_name)
+            .save_file();
+
+        let message_sender = self.message_channel.0.clone();
+
+        execute(async move {
+            let file = task.await;
+
+            if let Some(file) = file {
+                let file_path = std::path::PathBuf::from(file.path());
+                message_sender.send(Message::FileSave(file_path)).ok();
+            }
+        });
+    }
+}
+
+/// Lists the values `query` selects in `body`, or why it is invalid. Clicking the path of a value
+/// expands the tree down to it.
+fn show_query_matches(ui: &mut Ui, query: &str, body: &[Element], expanded: &mut HashSet<ValuePath>) {
+    let matches = match query.parse::<Query>() {
+        Ok(query) => query.run(body),
+        Err(error) => {
+            ui.add(egui::Label::new(error.to_string()).text_color(Color32::RED));
+            return;
+        }
+    };
+
+    ui.label(format!("{} matches", matches.len()));
+    egui::ScrollArea::vertical()
+        .id_source("query_matches")
+        .max_height(150.0)
+        .show(ui, |ui| {
+            egui::Grid::new("query_matches").striped(true).show(ui, |ui| {
+                for found in &matches {
+                    if ui.small_button(found.path.to_string()).on_hover_text("Show in the tree").clicked() {
+                        expanded.extend(found.path.prefixes());
+                    }
+                    ui.label(value::describe(&found.value));
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+/// State shared by every row while the body of the document is drawn.
+struct BodyContext<'a> {
+    dirty: &'a mut HashSet<ValuePath>,
+    decoded: &'a mut HashMap<ValuePath, Option<NestedValue>>,
+    expanded: &'a mut HashSet<ValuePath>,
+    expand_all: bool,
+    message_sender: &'a Sender<Message>,
+    classes: &'a CustomClasses,
+    /// Edits to the elements of containers, made once the body has been drawn.
+    edits: &'a mut Vec<Edit>,
+    /// Drawing a value paths can't reach, decoded from a ByteArray or the key of a Dictionary
+    /// pair, so element edits are made in place and end up in the history as a change of the
+    /// value holding it.
+    unreachable: bool,
+    /// The encoding of the values being drawn, new values are created for it.
+    version: AMFVersion,
+    renaming: &'a mut Option<Renaming>,
+    dragging: &'a mut Option<(ValuePath, usize)>,
+    retyping: &'a mut Option<Retyping>,
+    /// Values found by the search, their rows are highlighted.
+    hits: &'a HashSet<ValuePath>,
+    scroll_to: &'a mut Option<ValuePath>,
+}
+
+fn execute<F: std::future::Future<Output = ()> + Send + 'static>(f: F) {
+    std::thread::spawn(move || {
+        futures::executor::block_on(f);
+    });
+}
+
+/// Draws `element` and its children with edit widgets for primitive values.
+/// Returns whether the value of `element` was changed this frame.
+fn process_element(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    element: &mut Element,
+    path: &ValuePath,
+) -> bool {
+    let text = format!("{} {}", type_icon(element.value()), element.name);
+    row_label(ui, context, &text, path, egui::Sense::hover());
+
+    process_element_value(ui, context, element, path)
+}
+
+/// Draws the value of `element`, returns whether it was changed this frame.
+fn process_element_value(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    element: &mut Element,
+    path: &ValuePath,
+) -> bool {
+    match process_value(ui, context, element.value(), path) {
+        Some(value) => {
+            element.value = Rc::new(value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Labels a row, highlighting it if its value was edited or found by the search.
+fn row_label(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    text: &str,
+    path: &ValuePath,
+    sense: egui::Sense,
+) -> egui::Response {
+    let mut label = if context.dirty.contains(path) {
+        egui::Label::new(format!("{} *", text)).text_color(DIRTY_COLOR)
+    } else {
+        egui::Label::new(text)
+    };
+    if context.hits.contains(path) {
+        label = label.background_color(SEARCH_HIGHLIGHT_COLOR);
+    }
+
+    let response = ui.add(label.sense(sense));
+    if context.scroll_to.as_ref() == Some(path) {
+        response.scroll_to_me(egui::Align::Center);
+        *context.scroll_to = None;
+    }
+
+    response
+}
+
+/// An icon showing the type of `value` in front of its name.
+fn type_icon(value: &Value) -> &'static str {
+    match value {
+        Value::Number(_) | Value::Integer(_) => "🔢",
+        Value::Bool(_) => "☑",
+        Value::String(_) => "🔤",
+        Value::Object(_, _) => "📦",
+        Value::Null | Value::Undefined | Value::Unsupported => "∅",
+        Value::ECMAArray(_, _, _)
+        | Value::StrictArray(_)
+        | Value::VectorInt(_, _)
+        | Value::VectorUInt(_, _)
+        | Value::VectorDouble(_, _)
+        | Value::VectorObject(_, _, _) => "📚",
+        Value::Date(_, _) => "📅",
+        Value::XML(_, _) => "📄",
+        Value::AMF3(inner) => type_icon(inner),
+        Value::ByteArray(_) => "💾",
+        Value::Dictionary(_, _) => "📖",
+        Value::Custom(_, _, _) => "🧩",
+    }
+}
+
+/// Draws a tree node header that toggles whether the node is expanded, and `add_body` below it
+/// when it is. Collapsed nodes don't draw or copy anything below them.
+fn tree_node<R>(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    title: String,
+    path: &ValuePath,
+    add_body: impl FnOnce(&mut Ui, &mut BodyContext<'_>) -> R,
+) -> egui::InnerResponse<Option<R>> {
+    if context.expand_all {
+        context.expanded.insert(path.clone());
+    }
+
+    let expanded = context.expanded.contains(path);
+
+    ui.vertical(|ui| {
+        let arrow = if expanded { "▼" } else { "▶" };
+        if ui.add(egui::Button::new(format!("{} {}", arrow, title)).frame(false)).clicked() {
+            if expanded {
+                context.expanded.remove(path);
+            } else {
+                context.expanded.insert(path.clone());
+            }
+        }
+
+        if expanded {
+            Some(ui.indent(path, |ui| add_body(ui, context)).inner)
+        } else {
+            None
+        }
+    })
+}
+
+/// Draws the edit widgets for `value`, returning its replacement if it was edited.
+fn process_value(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    value: &Value,
+    path: &ValuePath,
+) -> Option<Value> {
+    // Primitive edits mark the row dirty, edits to children only replace the value
+    let mut edited = false;
+    let mut new_value = None;
+
+    match value {
+        Value::Number(number) => {
+            let mut number = *number;
+            let response = ui.add(egui::DragValue::new(&mut number));
+
+            if response.changed() {
+                edited = true;
+                new_value = Some(Value::Number(number));
+            }
+
+            response
+        },
+        Value::Bool(bool) => {
+            let mut bool = *bool;
+            let response = ui.checkbox(&mut bool, "");
+
+            if response.changed() {
+                edited = true;
+                new_value = Some(Value::Bool(bool));
+            }
+
+            response
+        },
+        Value::String(string) => {
+            let mut string = string.clone();
+            let response = ui.text_edit_singleline(&mut string);
+
+            if response.changed() {
+                edited = true;
+                new_value = Some(Value::String(string));
+            }
+
+            response
+        },
+        Value::Object(elements, class_definition) => {
+            let title = match class_definition {
+                Some(class_definition) if !class_definition.name.is_empty() => {
+                    format!("Object {} {{{}}}", class_definition.name, elements.len())
+                }
+                _ => format!("Object {{{}}}", elements.len()),
+            };
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                match class_definition {
+                    Some(class_definition) => {
+                        ui.horizontal(|ui| {
+                            ui.label("Class Definition:");
+                            ui.code(format!("{:?}", class_definition.name));
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Static Properties:");
+                            ui.code(format!("{:?}", class_definition.static_properties));
+                        });
+                    }
+                    None => {
+                        ui.code("No Class Definition Found!");
+                    }
+                }
+
+                let mut elements = elements.clone();
+                let changed = process_elements(ui, context, &mut elements, path, "properties");
+                changed.then(|| Value::Object(elements, class_definition.clone()))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        },
+        Value::Null => {
+            ui.horizontal(|ui| {
+                ui.code("null");
+            }).response
+        },
+        Value::Undefined => {ui.code("undefined")},
+        Value::ECMAArray(dense, associative, length) => {
+            let title = format!("ECMAArray (length {})", length);
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut dense = dense.clone();
+                let mut associative = associative.clone();
+                let mut length = *length;
+                let associative_count = associative.len();
+                let mut changed = false;
+
+                ui.horizontal(|ui| {
+                    ui.label("Declared length:");
+                    ui.code(length);
+
+                    if length as usize != associative_count {
+                        ui.add(egui::Label::new(format!("({} associative entries)", associative_count)).text_color(Color32::RED));
+                    }
+                });
+
+                ui.label("Dense part:");
+                if context.version == AMFVersion::AMF0 {
+                    let warning = "AMF0 arrays have no dense part, it isn't saved";
+                    ui.add(egui::Label::new(warning).text_color(CHANGED_COLOR));
+                    ui.scope(|ui| {
+                        ui.set_enabled(false);
+                        process_dense(ui, context, &mut dense.clone(), path);
+                    });
+                } else {
+                    changed |= process_dense(ui, context, &mut dense, path);
+                }
+
+                ui.label("Associative part:");
+                changed |= process_elements(ui, context, &mut associative, path, "associative");
+
+                // Keep the declared length in step with added or removed entries
+                let added = associative.len() as i64 - associative_count as i64;
+                length = (length as i64 + added).max(0) as u32;
+
+                changed.then_some(Value::ECMAArray(dense, associative, length))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        },
+        Value::StrictArray(values) => {
+            let title = format!("StrictArray [{}]", values.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut values = values.clone();
+                let changed = process_dense(ui, context, &mut values, path);
+                changed.then_some(Value::StrictArray(values))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        },
+        Value::Date(millis, timezone) => {
+            let mut millis = *millis;
+            let mut timezone = *timezone;
+            let mut changed = false;
+
+            let response = ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    match DateTime::from_millis(millis) {
+                        Some(date_time) => ui.code(date_time),
+                        None => ui.code("Invalid Date"),
+                    };
+
+                    changed |= ui.add(egui::DragValue::new(&mut millis).suffix(" ms")).changed();
+
+                    if let Some(timezone) = &mut timezone {
+                        ui.code(date::format_timezone(*timezone));
+
+                        // The timezone is a signed offset in minutes stored as a u16
+                        let mut offset = *timezone as i16;
+                        if ui.add(egui::DragValue::new(&mut offset).suffix(" min")).changed() {
+                            *timezone = offset as u16;
+                            changed = true;
+                        }
+                    }
+                });
+
+                egui::CollapsingHeader::new("Pick date")
+                    .id_source((path, "date"))
+                    .show(ui, |ui| {
+                        changed |= date_picker(ui, &mut millis);
+                    });
+            }).response;
+
+            if changed {
+                edited = true;
+                new_value = Some(Value::Date(millis, timezone));
+            }
+
+            response
+        },
+        Value::Unsupported => {ui.code("unsupported")},
+        Value::XML(string, bool) => {
+            let mut string = string.clone();
+            let is_string = *bool;
+
+            ui.horizontal(|ui| {
+                if ui.text_edit_multiline(&mut string).changed() {
+                    edited = true;
+                    new_value = Some(Value::XML(string, is_string));
+                }
+                ui.code(is_string);
+            }).response
+        }
+        Value::AMF3(inner) => {
+            let response = ui.horizontal(|ui| {
+                ui.add(egui::Label::new("AMF3").small().text_color(AMF3_BADGE_COLOR))
+                    .on_hover_text("Stored with AMF3 encoding inside this AMF0 file");
+
+                let version = std::mem::replace(&mut context.version, AMFVersion::AMF3);
+                let value = process_value(ui, context, inner, path);
+                context.version = version;
+                value
+            });
+
+            // Keep the wrapper so the value is written with AMF3 encoding again
+            if let Some(value) = response.inner {
+                new_value = Some(Value::AMF3(Rc::new(value)));
+            }
+
+            response.response
+        }
+        Value::Integer(integer) => {
+            let mut integer = *integer;
+            let response = ui.add(
+                egui::DragValue::new(&mut integer)
+                    .clamp_range(value::INTEGER_MIN..=value::INTEGER_MAX),
+            );
+
+            if response.changed() {
+                edited = true;
+                new_value = Some(Value::Integer(integer));
+            }
+
+            response
+        }
+        Value::ByteArray(bytes) => {
+            let title = format!("ByteArray ({} bytes)", bytes.len());
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                process_byte_array(ui, context, bytes, path)
+            });
+
+            if let Some(Some(bytes)) = response.inner {
+                edited = true;
+                new_value = Some(Value::ByteArray(bytes));
+            }
+
+            response.response
+        }
+        Value::VectorInt(items, fixed_length) => {
+            let title = format!("VectorInt [{}]", items.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut items = items.clone();
+                let mut fixed_length = *fixed_length;
+
+                let changed = ui.checkbox(&mut fixed_length, "Fixed length").changed();
+                let changed = changed | process_list(ui, context, &mut items, fixed_length, path, || 0, process_integer);
+                changed.then_some(Value::VectorInt(items, fixed_length))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+        Value::VectorUInt(items, fixed_length) => {
+            let title = format!("VectorUInt [{}]", items.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut items = items.clone();
+                let mut fixed_length = *fixed_length;
+
+                let changed = ui.checkbox(&mut fixed_length, "Fixed length").changed();
+                let changed = changed | process_list(ui, context, &mut items, fixed_length, path, || 0, process_integer);
+                changed.then_some(Value::VectorUInt(items, fixed_length))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+        Value::VectorDouble(items, fixed_length) => {
+            let title = format!("VectorDouble [{}]", items.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut items = items.clone();
+                let mut fixed_length = *fixed_length;
+
+                let changed = ui.checkbox(&mut fixed_length, "Fixed length").changed();
+                let changed = changed | process_list(ui, context, &mut items, fixed_length, path, || 0.0, process_double);
+                changed.then_some(Value::VectorDouble(items, fixed_length))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+        Value::VectorObject(items, type_name, fixed_length) => {
+            let title = format!("VectorObject<{}> [{}]", type_name, items.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut items = items.clone();
+                let mut type_name = type_name.clone();
+                let mut fixed_length = *fixed_length;
+
+                let mut changed = ui.checkbox(&mut fixed_length, "Fixed length").changed();
+
+                ui.horizontal(|ui| {
+                    ui.label("Element type:");
+                    changed |= ui.text_edit_singleline(&mut type_name).changed();
+                });
+
+                changed |= process_list(ui, context, &mut items, fixed_length, path, || Rc::new(Value::Null), process_entry);
+                changed.then_some(Value::VectorObject(items, type_name, fixed_length))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+        Value::Dictionary(pairs, weak_keys) => {
+            let title = format!("Dictionary [{}]", pairs.len());
+
+            let response = tree_node(ui, context, title, path, |ui, context| {
+                let mut pairs = pairs.clone();
+                let mut weak_keys = *weak_keys;
+
+                let changed = ui.checkbox(&mut weak_keys, "Weak keys").changed();
+                let changed = changed | process_dictionary(ui, context, &mut pairs, path);
+                changed.then_some(Value::Dictionary(pairs, weak_keys))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+        Value::Custom(custom_elements, elements, class_definition) => {
+            let class_name = class_definition.as_ref().map_or("", |class_definition| &class_definition.name);
+
+            let response = tree_node(ui, context, format!("Custom {}", class_name), path, |ui, context| {
+                let mut custom_elements = custom_elements.clone();
+                let mut elements = elements.clone();
+
+                if !context.classes.has_decoder(class_name) {
+                    ui.add(
+                        egui::Label::new(format!(
+                            "No decoder for this class, its data is kept as the raw bytes in \"{}\".",
+                            custom::RAW_ELEMENT
+                        ))
+                        .text_color(Color32::RED),
+                    );
+                }
+
+                ui.label("External data:");
+                let mut changed = process_properties(ui, context, &mut custom_elements, path, "external");
+
+                ui.label("Properties:");
+                changed |= process_elements(ui, context, &mut elements, path, "properties");
+                changed.then(|| Value::Custom(custom_elements, elements, class_definition.clone()))
+            });
+
+            if let Some(Some(value)) = response.inner {
+                new_value = Some(value);
+            }
+
+            response.response
+        }
+    };
+
+    if edited {
+        context.dirty.insert(path.clone());
+    }
+
+    new_value
+}
+
+/// Draws a hex dump of `bytes` and the value decoded from them, returns the new bytes if they
+/// were replaced from a file or the decoded value was edited.
+fn process_byte_array(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    bytes: &[u8],
+    path: &ValuePath,
+) -> Option<Vec<u8>> {
+    let mut new_bytes = None;
+
+    ui.horizontal(|ui| {
+        if ui.button("Export...").clicked() {
+            export_bytes(context.message_sender.clone(), bytes.to_vec());
+        }
+
+        if ui.button("Replace from file...").clicked() {
+            import_bytes(context.message_sender.clone(), path.clone());
+        }
+
+        if ui.button("Decode").clicked() {
+            context.decoded.insert(path.clone(), byte_array::decode(bytes, context.classes));
+        }
+    });
+
+    let row_height = ui.fonts()[egui::TextStyle::Monospace].row_height();
+    egui::ScrollArea::vertical()
+        .id_source((path, "hex"))
+        .max_height(row_height * 16.0)
+        .show_rows(ui, row_height, byte_array::hex_dump_lines(bytes), |ui, lines| {
+            for line in lines {
+                ui.monospace(byte_array::hex_dump_line(bytes, line));
+            }
+        });
+
+    match context.decoded.get(path).cloned() {
+        Some(Some(mut nested)) => {
+            ui.label(if nested.compressed {
+                "Decoded as zlib compressed AMF3:"
+            } else {
+                "Decoded as AMF3:"
+            });
+
+            let nested_path = path.child("<decoded>");
+            let unreachable = std::mem::replace(&mut context.unreachable, true);
+            let version = std::mem::replace(&mut context.version, AMFVersion::AMF3);
+            let value = process_value(ui, context, &nested.value, &nested_path);
+            context.unreachable = unreachable;
+            context.version = version;
+
+            if let Some(value) = value {
+                nested.value = value;
+                new_bytes = Some(byte_array::encode(&nested, context.classes));
+                context.decoded.insert(path.clone(), Some(nested));
+            }
+        }
+        Some(None) => {
+            ui.add(egui::Label::new("Not AMF3 or zlib compressed AMF3 data.").text_color(Color32::RED));
+        }
+        None => {}
+    }
+
+    new_bytes
+}
+
+/// Asks for a file to write `bytes` to.
+fn export_bytes(message_sender: Sender<Message>, bytes: Vec<u8>) {
+    let task = rfd::AsyncFileDialog::new()
+        .set_directory(std::env::current_dir().unwrap())
+        .save_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::ExportBytes(file_path, bytes)).ok();
+        }
+    });
+}
+
+/// Asks for a file to replace the ByteArray at `path` with.
+fn import_bytes(message_sender: Sender<Message>, path: ValuePath) {
+    let task = rfd::AsyncFileDialog::new()
+        .set_directory(std::env::current_dir().unwrap())
+        .pick_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::ImportBytes(file_path, path)).ok();
+        }
+    });
+}
+
+/// Asks for a file to export the document to as `format`.
+fn export_text(message_sender: Sender<Message>, name: &str, format: Format) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter(&format!("{} files", format.name()), format.extensions())
+        .set_directory(std::env::current_dir().unwrap())
+        .set_file_name(&format!("{}.{}", name, format.extensions()[0]))
+        .save_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::Export(file_path, format)).ok();
+        }
+    });
+}
+
+/// Asks for a SOL file to compare the document with.
+fn compare_with(message_sender: Sender<Message>) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter("SOL files", &["sol"])
+        .set_directory(std::env::current_dir().unwrap())
+        .pick_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::Compare(file_path)).ok();
+        }
+    });
+}
+
+/// Asks for the common base of a merge, the version both the document and theirs started from.
+fn pick_merge_base(message_sender: Sender<Message>) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter("SOL files", &["sol"])
+        .set_directory(std::env::current_dir().unwrap())
+        .set_title("Merge: pick the common base")
+        .pick_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::MergeBase(file_path)).ok();
+        }
+    });
+}
+
+/// Asks for their version of the document to merge from `base_path`.
+fn pick_theirs(message_sender: Sender<Message>, base_path: PathBuf) {
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter("SOL files", &["sol"])
+        .set_directory(base_path.parent().map(Path::to_path_buf).unwrap_or_default())
+        .set_title("Merge: pick their version")
+        .pick_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::Merge(base_path, file_path)).ok();
+        }
+    });
+}
+
+/// Asks for a text export to open.
+fn import_text(message_sender: Sender<Message>) {
+    let extensions: Vec<&str> = Format::ALL.iter().flat_map(|format| format.extensions()).copied().collect();
+    let task = rfd::AsyncFileDialog::new()
+        .add_filter("Exported documents", &extensions)
+        .set_directory(std::env::current_dir().unwrap())
+        .pick_file();
+
+    execute(async move {
+        let file = task.await;
+
+        if let Some(file) = file {
+            let file_path = std::path::PathBuf::from(file.path());
+            message_sender.send(Message::Import(file_path)).ok();
+        }
+    });
+}
+
+/// Draws calendar fields for the date `millis` since the epoch, returns whether it changed.
+fn date_picker(ui: &mut Ui, millis: &mut f64) -> bool {
+    // Invalid dates start from the epoch so a valid one can be picked
+    let before = DateTime::from_millis(*millis).unwrap_or_else(|| DateTime::from_millis(0.0).unwrap());
+    let fraction = if millis.is_finite() { *millis - millis.floor() } else { 0.0 };
+    let mut date_time = before;
+
+    ui.horizontal(|ui| {
+        ui.label("Date:");
+        ui.add(egui::DragValue::new(&mut date_time.year).clamp_range(-271_821..=275_760).speed(0.1));
+        ui.label("-");
+        ui.add(egui::DragValue::new(&mut date_time.month).clamp_range(1..=12).speed(0.1));
+        ui.label("-");
+        let days = date::days_in_month(date_time.year, date_time.month);
+        ui.add(egui::DragValue::new(&mut date_time.day).clamp_range(1..=days).speed(0.1));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Time:");
+        ui.add(egui::DragValue::new(&mut date_time.hour).clamp_range(0..=23).speed(0.1));
+        ui.label(":");
+        ui.add(egui::DragValue::new(&mut date_time.minute).clamp_range(0..=59).speed(0.1));
+        ui.label(":");
+        ui.add(egui::DragValue::new(&mut date_time.second).clamp_range(0..=59).speed(0.1));
+        ui.label(".");
+        ui.add(egui::DragValue::new(&mut date_time.millisecond).clamp_range(0..=999));
+    });
+
+    // Changing the month or year can leave the day past the end of the month
+    date_time.day = date_time.day.min(date::days_in_month(date_time.year, date_time.month));
+
+    if date_time != before {
+        *millis = date_time.to_millis() + fraction;
+        true
+    } else {
+        false
+    }
+}
+
+/// Draws the positional entries of an array with their index, returns whether any changed.
+fn process_dense(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    values: &mut Vec<Rc<Value>>,
+    path: &ValuePath,
+) -> bool {
+    process_list(ui, context, values, false, path, || Rc::new(Value::Null), process_entry)
+}
+
+/// Draws the properties of an object, returns whether any of them changed.
+fn process_properties(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    elements: &mut [Element],
+    path: &ValuePath,
+    grid: &str,
+) -> bool {
+    let mut changed = false;
+
+    egui::Grid::new((path, grid)).striped(true).show(ui, |ui| {
+        for element in elements.iter_mut() {
+            let element_path = path.child(&element.name);
+            changed |= process_element(ui, context, element, &element_path);
+            ui.end_row();
+        }
+    });
+
+    changed
+}
+
+/// Draws an entry of an array or object vector, returns whether it changed.
+fn process_entry(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    value: &mut Rc<Value>,
+    path: &ValuePath,
+) -> bool {
+    match process_value(ui, context, value, path) {
+        Some(new_value) => {
+            *value = Rc::new(new_value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A change made to a list through the buttons on one of its rows.
+enum ListEdit {
+    Remove(usize),
+    MoveUp(usize),
+    MoveDown(usize),
+}
+
+/// An item of a list drawn by `process_list`.
+trait ListItem {
+    /// The item as a value whose type can be changed, vector items have a fixed type.
+    fn as_value(&self) -> Option<&Value> {
+        None
+    }
+}
+
+impl ListItem for Rc<Value> {
+    fn as_value(&self) -> Option<&Value> {
+        Some(self)
+    }
+}
+
+impl ListItem for i32 {}
+impl ListItem for u32 {}
+impl ListItem for f64 {}
+
+/// Draws `items` with their index and buttons to add, remove and reorder them, adding and removing
+/// is disabled for fixed length lists. Returns whether the list or any item in it changed.
+fn process_list<T: ListItem>(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    items: &mut Vec<T>,
+    fixed_length: bool,
+    path: &ValuePath,
+    new_item: impl Fn() -> T,
+    mut process_item: impl FnMut(&mut Ui, &mut BodyContext<'_>, &mut T, &ValuePath) -> bool,
+) -> bool {
+    let mut changed = false;
+    let mut list_edit = None;
+    let last = items.len().saturating_sub(1);
+
+    egui::Grid::new((path, "list")).striped(true).show(ui, |ui| {
+        for (index, item) in items.iter_mut().enumerate() {
+            let item_path = path.index(index);
+
+            match item.as_value() {
+                Some(value) => entry_label(ui, context, &index.to_string(), value, &item_path),
+                None => {
+                    row_label(ui, context, &index.to_string(), &item_path, egui::Sense::hover());
+                }
+            }
+            changed |= process_item(ui, context, item, &item_path);
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(index > 0, egui::Button::new("⬆").small()).on_hover_text("Move up").clicked() {
+                    list_edit = Some(ListEdit::MoveUp(index));
+                }
+                if ui.add_enabled(index < last, egui::Button::new("⬇").small()).on_hover_text("Move down").clicked() {
+                    list_edit = Some(ListEdit::MoveDown(index));
+                }
+                if ui.add_enabled(!fixed_length, egui::Button::new("🗑").small()).on_hover_text("Remove entry").clicked() {
+                    list_edit = Some(ListEdit::Remove(index));
+                }
+            });
+
+            ui.end_row();
+        }
+    });
+
+    match list_edit {
+        Some(ListEdit::Remove(index)) => {
+            items.remove(index);
+        }
+        Some(ListEdit::MoveUp(index)) => items.swap(index - 1, index),
+        Some(ListEdit::MoveDown(index)) => items.swap(index, index + 1),
+        None => {}
+    }
+    changed |= list_edit.is_some();
+
+    if ui.add_enabled(!fixed_length, egui::Button::new("Add entry").small()).clicked() {
+        items.push(new_item());
+        changed = true;
+    }
+
+    changed
+}
+
+/// Draws an integer entry of a vector, input that `T` can't hold is rejected.
+/// Returns whether the entry changed.
+fn process_integer<T>(ui: &mut Ui, context: &mut BodyContext<'_>, item: &mut T, path: &ValuePath) -> bool
+where
+    T: Copy + Into<f64> + TryFrom<i64>,
+{
+    let response = ui.add(
+        egui::DragValue::from_get_set(|new| {
+            if let Some(new) = new {
+                if let Ok(new) = T::try_from(new.round() as i64) {
+                    *item = new;
+                }
+            }
+
+            (*item).into()
+        })
+        .max_decimals(0),
+    );
+
+    if response.changed() {
+        context.dirty.insert(path.clone());
+    }
+
+    response.changed()
+}
+
+fn process_double(ui: &mut Ui, context: &mut BodyContext<'_>, item: &mut f64, path: &ValuePath) -> bool {
+    let changed = ui.add(egui::DragValue::new(item)).changed();
+
+    if changed {
+        context.dirty.insert(path.clone());
+    }
+
+    changed
+}
+
+/// Draws the key/value pairs of a dictionary, returns whether any changed.
+fn process_dictionary(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    pairs: &mut Vec<(Rc<Value>, Rc<Value>)>,
+    path: &ValuePath,
+) -> bool {
+    let mut changed = false;
+    let mut removed = None;
+
+    egui::Grid::new((path, "dictionary")).striped(true).show(ui, |ui| {
+        ui.label("");
+        ui.strong("Key");
+        ui.strong("Value");
+        ui.end_row();
+
+        for (index, (key, value)) in pairs.iter_mut().enumerate() {
+            let value_path = path.index(index);
+
+            entry_label(ui, context, &index.to_string(), value, &value_path);
+            // Paths only lead to the values of pairs, keys are changed as part of the dictionary
+            let unreachable = std::mem::replace(&mut context.unreachable, true);
+            changed |= process_entry(ui, context, key, &value_path.child("<key>"));
+            context.unreachable = unreachable;
+            changed |= process_entry(ui, context, value, &value_path);
+
+            if ui.small_button("🗑").on_hover_text("Remove pair").clicked() {
+                removed = Some(index);
+            }
+
+            ui.end_row();
+        }
+    });
+
+    if let Some(index) = removed {
+        pairs.remove(index);
+        changed = true;
+    }
+
+    if ui.small_button("Add pair").clicked() {
+        let key = Value::String(format!("key{}", pairs.len()));
+        pairs.push((Rc::new(key), Rc::new(Value::Null)));
+        changed = true;
+    }
+
+    changed
+}
+
+/// Draws `elements`, the properties of the value at `parent`. Right clicking the name of an
+/// element opens a menu to insert, rename, duplicate and delete elements, dragging it moves the
+/// element. Returns whether the value of any element changed, the elements themselves change
+/// through edits.
+fn process_elements(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    elements: &mut Vec<Element>,
+    parent: &ValuePath,
+    grid: &str,
+) -> bool {
+    let mut changed = false;
+    let mut edit = None;
+
+    egui::Grid::new((parent, grid)).striped(true).show(ui, |ui| {
+        for index in 0..elements.len() {
+            let path = parent.child(&elements[index].name);
+            let name = element_name(ui, context, elements, index, parent, &mut edit);
+            changed |= process_element_value(ui, context, &mut elements[index], &path);
+
+            if name.drag_started() {
+                *context.dragging = Some((parent.clone(), index));
+            }
+
+            // Show where the dragged element would land, above or below this row
+            if let Some((dragged_parent, from)) = context.dragging.clone() {
+                let (top, bottom) = (name.rect.top(), ui.min_rect().bottom());
+                let hovered = match ui.input().pointer.hover_pos() {
+                    Some(pointer) => (top..bottom).contains(&pointer.y),
+                    None => false,
+                };
+
+                if dragged_parent == *parent && from != index && hovered {
+                    let y = if index < from { top } else { bottom };
+                    let stroke = ui.visuals().selection.stroke;
+                    ui.painter().line_segment([egui::pos2(name.rect.left(), y), egui::pos2(ui.min_rect().right(), y)], stroke);
+
+                    if ui.input().pointer.any_released() {
+                        edit = Some(Edit::Move {
+                            parent: parent.clone(),
+                            from,
+                            to: index,
+                        });
+                    }
+                }
+            }
+
+            ui.end_row();
+        }
+    });
+
+    if ui.small_button("Add element").clicked() {
+        edit = Some(insert_element(context, elements, parent, elements.len(), "element", Value::Null));
+    }
+
+    if let Some(edit) = edit {
+        changed |= edit_elements(context, elements, edit);
+    }
+
+    changed
+}
+
+/// Draws the name of the element at `index` with its menu, or the field renaming it.
+fn element_name(
+    ui: &mut Ui,
+    context: &mut BodyContext<'_>,
+    elements: &[Element],
+    index: usize,
+    parent: &ValuePath,
+    edit: &mut Option<Edit>,
+) -> egui::Response {
+    let element = &elements[index];
+    let path = parent.child(&element.name);
+
+    if let Some(renaming) = context.renaming.as_mut().filter(|renaming| renaming.parent == *parent && renaming.index == index) {
+        // Names identify elements, so they have to be unique within their parent
+        let problem = if renaming.name.is_empty() {
+            Some("Names can't be empty".to_string())
+        } else if elements.iter().enumerate().any(|(other, element)| other != index && element.name == renaming.name) {
+            Some(format!("There already is an element called {:?} here", renaming.name))
+        } else {
+            None
+        };
+
+        let field = egui::TextEdit::singleline(&mut renaming.name)
+            .desired_width(120.0)
+            .text_color_opt(problem.as_ref().map(|_| Color32::RED));
+        let mut response = ui.add(field);
+        if renaming.focus {
+            response.request_focus();
+            renaming.focus = false;
+        }
+        if let Some(problem) = &problem {
+            response = response.on_hover_text(problem);
+        }
+
+        if response.lost_focus() {
+            if problem.is_some() && ui.input().key_pressed(egui::Key::Enter) {
+                // Keep editing until the name can be used
+                response.request_focus();
+            } else {
+                let cancelled = ui.input().key_pressed(egui::Key::Escape);
+                if problem.is_none() && !cancelled && renaming.name != element.name {
+                    *edit = Some(Edit::Rename {
+                        parent: parent.clone(),
+                        index,
+                        old: element.name.clone(),
+                        new: renaming.name.clone(),
+                    });
+                }
+                *context.renaming = None;
+            }
+        }
+
+        return response;
+    }
+
+    let text = format!("{} {}", type_icon(element.value()), element.name);
+    let response = row_label(ui, context, &text, &path, egui::Sense::click_and_drag())
+        .on_hover_cursor(egui::CursorIcon::Grab)
+        .on_hover_text("Right click for actions, drag to move");
+
+    let menu_id = ui.make_persistent_id((&path, "element_menu"));
+    row_menu(ui, &response, menu_id, |ui| {
+        ui.set_max_width(240.0);
+
+        if ui.button("Rename").clicked() {
+            *context.renaming = Some(Renaming {
+                parent: parent.clone(),
+                index,
+                name: element.name.clone(),
+                focus: true,
+            });
+        }
+        if ui.button("Duplicate").clicked() {
+            let name = format!("{}_copy", element.name);
+            let value = element.value().clone();
+            *edit = Some(insert_element(context, elements, parent, index + 1, &name, value));
+        }
+        if ui.button("Delete").clicked() {
+            *edit = Some(Edit::Delete {
+                parent: parent.clone(),
+                index,
+                element: element.clone(),
+            });
+        }
+        change_type_button(ui, context, element.value(), &path);
+
+        ui.separator();
+        ui.label("Insert after:");
+        ui.horizontal_wrapped(|ui| {
+            for type_name in value::new_types(context.version) {
+                if ui.small_button(type_name).clicked() {
+                    if let Some(value) = value::default_value(type_name, context.version) {
+                        *edit = Some(insert_element(context, elements, parent, index + 1, "element", value));
+                    }
+                }
+            }
+        });
+    });
+
+    response
+}
+
+/// Opens a menu below `response` when it is right clicked, and draws it while it is open.
+fn row_menu(ui: &Ui, response: &egui::Response, id: egui::Id, add_contents: impl FnOnce(&mut Ui)) {
+    if response.secondary_clicked() {
+        ui.memory().toggle_popup(id);
+    }
+    if !ui.memory().is_popup_open(id) {
+        return;
+    }
+
+    egui::Area::new(id)
+        .order(egui::Order::Foreground)
+        .fixed_pos(response.rect.left_bottom())
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, add_contents);
+        });
+
+    if ui.input().key_pressed(egui::Key::Escape) || response.clicked_elsewhere() {
+        ui.memory().close_popup();
+    }
+}
+
+/// Labels an array or dictionary entry, right clicking it opens a menu to change its type.
+fn entry_label(ui: &mut Ui, context: &mut BodyContext<'_>, text: &str, value: &Value, path: &ValuePath) {
+    let response = row_label(ui, context, text, path, egui::Sense::click());
+    let menu_id = ui.make_persistent_id((path, "entry_menu"));
+
+    row_menu(ui, &response, menu_id, |ui| change_type_button(ui, context, value, path));
+}
+
+/// The menu button opening the change type window for `value`. Values decoded from ByteArrays and
+/// Dictionary keys can't be reached by their path, so their type can't be changed.
+fn change_type_button(ui: &mut Ui, context: &mut BodyContext<'_>, value: &Value, path: &ValuePath) {
+    let targets = retype::targets(value, context.version);
+    let button = egui::Button::new("Change type...");
+
+    if ui.add_enabled(!context.unreachable && !targets.is_empty(), button).clicked() {
+        *context.retyping = Some(Retyping {
+            path: path.clone(),
+            target: targets[0],
+        });
+    }
+}
+
+/// The edit inserting `value` at `index` under a unique name starting with `name`, which is
+/// then edited.
+fn insert_element(
+    context: &mut BodyContext<'_>,
+    elements: &[Element],
+    parent: &ValuePath,
+    index: usize,
+    name: &str,
+    value: Value,
+) -> Edit {
+    let taken = |name: &str| elements.iter().any(|element| element.name == name);
+    let name = std::iter::once(name.to_string())
+        .chain((2..).map(|number| format!("{}{}", name, number)))
+        .find(|name| !taken(name))
+        .unwrap();
+
+    *context.renaming = Some(Renaming {
+        parent: parent.clone(),
+        index,
+        name: name.clone(),
+        focus: true,
+    });
+
+    Edit::Insert {
+        parent: parent.clone(),
+        index,
+        element: Element::new(name, value),
+    }
+}
+
+/// Makes an edit to the entries of the container being drawn. Edits inside values paths can't
+/// reach are made right away, returning true, anything else is made once the body has been drawn.
+fn edit_elements(context: &mut BodyContext<'_>, elements: &mut Vec<Element>, edit: Edit) -> bool {
+    if context.unreachable {
+        edit.apply_to_elements(elements)
+    } else {
+        context.edits.push(edit);
+        false
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{Read, Write};
+
+use flash_lso::types::Value;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::amf3;
+use crate::custom::CustomClasses;
+
+/// Number of bytes shown on each line of a hex dump.
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Formats one line of a hex dump: the offset, the bytes in hex and their printable ASCII.
+/// `line` is the index of the line, so it starts at byte `line * BYTES_PER_LINE`.
+pub fn hex_dump_line(bytes: &[u8], line: usize) -> String {
+    let offset = line * BYTES_PER_LINE;
+    let chunk = &bytes[offset..bytes.len().min(offset + BYTES_PER_LINE)];
+
+    let mut hex = String::with_capacity(BYTES_PER_LINE * 3);
+    for (i, byte) in chunk.iter().enumerate() {
+        // Split the line in two halves for readability
+        if i == BYTES_PER_LINE / 2 {
+            hex.push(' ');
+        }
+        hex.push_str(&format!("{:02x} ", byte));
+    }
+
+    let ascii: String = chunk
+        .iter()
+        .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+        .collect();
+
+    format!("{:08x}  {:<49} |{}|", offset, hex, ascii)
+}
+
+pub fn hex_dump_lines(bytes: &[u8]) -> usize {
+    (bytes.len() + BYTES_PER_LINE - 1) / BYTES_PER_LINE
+}
+
+/// A value found by decoding the contents of a `Value::ByteArray`.
+#[derive(Clone, Debug)]
+pub struct NestedValue {
+    pub value: Value,
+    /// Whether the bytes were zlib compressed, as done by `ByteArray.compress()`.
+    pub compressed: bool,
+}
+
+/// Tries to read `bytes` as a single AMF3 value, as written by `ByteArray.writeObject()`,
+/// either directly or after zlib decompression.
+pub fn decode(bytes: &[u8], classes: &CustomClasses) -> Option<NestedValue> {
+    if let Some(value) = amf3::decode(bytes, classes) {
+        return Some(NestedValue {
+            value,
+            compressed: false,
+        });
+    }
+
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(bytes).read_to_end(&mut decompressed).ok()?;
+
+    amf3::decode(&decompressed, classes).map(|value| NestedValue {
+        value,
+        compressed: true,
+    })
+}
+
+/// Writes `nested` back to the bytes it was decoded from.
+pub fn encode(nested: &NestedValue, classes: &CustomClasses) -> Vec<u8> {
+    let bytes = amf3::encode(&nested.value, classes);
+
+    if nested.compressed {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        // Writing to a Vec can't fail
+        encoder.write_all(&bytes).unwrap();
+        encoder.finish().unwrap()
+    } else {
+        bytes
+    }
+}
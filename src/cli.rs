@@ -0,0 +1,573 @@
+//! Command line interface for inspecting and editing SOL files without a display.
+
+use std::convert::TryInto;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Element, Value};
+
+use crate::convert;
+use crate::custom::CustomClasses;
+use crate::date::DateTime;
+use crate::diff::{self, Change};
+use crate::document::{self, SolDocument};
+use crate::path::ValuePath;
+use crate::query::Query;
+use crate::script;
+use crate::text::Format;
+use crate::value;
+
+const USAGE: &str = "\
+Usage: sol_editor <command> [arguments]
+
+Commands:
+    dump <file>                             Print every value in the file
+    get <file> <path>                       Print the value at <path>
+    query <file> <query>                    Print the values <query> selects, exits with 1 if
+                                            there are none
+    set <file> <path> <value> [options]     Replace the value at <path>
+        --type <type>                       Type of the new value, defaults to the current type:
+                                            number, integer, bool, string, null or undefined
+        -o, --output <file>                 Write to <file> instead of changing <file> in place
+    convert <input> <output> --to <version> Convert the file to amf0 or amf3
+    validate <file>                         Check that the file can be read and written back
+    diff <old> <new>                        Print what changed between two files, exits with 1
+                                            if they differ
+    export <file> [<output>] [options]      Write the file as text, to stdout without <output>
+        --format <format>                   json, yaml or toml, defaults to the extension of
+                                            <output> or json
+    import <input> <output> [options]       Write a text export back to a SOL file
+        --format <format>                   Format of <input>, defaults to its extension
+    run-script <file> <script> [options]    Run the Rhai script in the file <script> on <file>
+                                            and save what it changed
+        -o, --output <file>                 Write to <file> instead of changing <file> in place
+
+Paths look like `player.inventory[3].count` or `[\"name with spaces\"]`. Queries are paths
+that can also hold wildcards like `inventory[*]`, `..count` to match at any depth, and filters
+like `..items[?(@.level > 10 && @.name != 'sword')]`.
+Scripts edit values with get(path), set(path, value), insert(parent, name or index, value),
+delete(path) and children(path), and print with print(text).
+Run without a command to start the editor, in builds with the `gui` feature.";
+
+/// Exit code for commands that failed.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid arguments.
+const EXIT_USAGE: i32 = 2;
+/// Exit code for files that differ, like `diff` uses.
+const EXIT_DIFFERENT: i32 = 1;
+/// Exit code for queries that selected nothing, like `grep` uses.
+const EXIT_NO_MATCH: i32 = 1;
+
+/// Why a command failed, along with the exit code to report it with.
+struct Failure {
+    message: String,
+    code: i32,
+}
+
+impl Failure {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code: EXIT_FAILURE,
+        }
+    }
+
+    fn usage(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            code: EXIT_USAGE,
+        }
+    }
+
+    /// Exits with `code` without printing anything, for commands that already printed their result.
+    fn silent(code: i32) -> Self {
+        Self {
+            message: String::new(),
+            code,
+        }
+    }
+}
+
+type CommandResult = Result<(), Failure>;
+
+/// Runs the command in `args`, which doesn't include the program name, and returns the exit code.
+pub fn run_cli(args: &[String]) -> i32 {
+    let classes = Rc::new(CustomClasses::default());
+
+    let result = match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "dump" => dump(args, classes),
+            "get" => get(args, classes),
+            "query" => query(args, classes),
+            "set" => set(args, classes),
+            "convert" => convert(args, classes),
+            "validate" => validate(args, classes),
+            "diff" => diff(args, classes),
+            "export" => export(args, classes),
+            "import" => import(args, classes),
+            "run-script" => run_script(args, classes),
+            "help" | "-h" | "--help" => {
+                println!("{}", USAGE);
+                Ok(())
+            }
+            command => Err(Failure::usage(format!("unknown command `{}`", command))),
+        },
+        None => Err(Failure::usage("no command given")),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(failure) if failure.message.is_empty() => failure.code,
+        Err(failure) => {
+            eprintln!("error: {}", failure.message);
+            if failure.code == EXIT_USAGE {
+                eprintln!("\n{}", USAGE);
+            }
+            failure.code
+        }
+    }
+}
+
+/// Splits `args` into positional arguments and the values of the options in `options`,
+/// each of which takes a value.
+fn parse_args<'a>(
+    args: &'a [String],
+    options: &[&[&str]],
+) -> Result<(Vec<&'a str>, Vec<Option<&'a str>>), Failure> {
+    let mut positional = Vec::new();
+    let mut values = vec![None; options.len()];
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match options.iter().position(|names| names.contains(&arg.as_str())) {
+            Some(index) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::usage(format!("`{}` needs a value", arg)))?;
+                values[index] = Some(value.as_str());
+            }
+            None if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(Failure::usage(format!("unknown option `{}`", arg)))
+            }
+            None => positional.push(arg.as_str()),
+        }
+    }
+
+    Ok((positional, values))
+}
+
+fn expect_arguments<'a, const N: usize>(positional: &[&'a str], names: &str) -> Result<[&'a str; N], Failure> {
+    positional
+        .try_into()
+        .map_err(|_| Failure::usage(format!("expected {}", names)))
+}
+
+fn open(path: &str, classes: Rc<CustomClasses>) -> Result<SolDocument, Failure> {
+    SolDocument::open(PathBuf::from(path), classes)
+        .map_err(|error| Failure::new(format!("failed to open {}: {}", path, error)))
+}
+
+fn parse_path(path: &str) -> Result<ValuePath, Failure> {
+    path.parse()
+        .map_err(|error| Failure::usage(format!("`{}` is not a valid path: {}", path, error)))
+}
+
+fn dump(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, _) = parse_args(args, &[])?;
+    let [file] = expect_arguments(&positional, "<file>")?;
+    let document = open(file, classes)?;
+
+    let header = &document.lso.header;
+    println!(
+        "# {:?}, {}, {} bytes",
+        header.name,
+        version_name(header.format_version),
+        header.length
+    );
+
+    for element in &document.lso.body {
+        dump_value(element.value(), &ValuePath::default().child(&element.name), "");
+    }
+
+    Ok(())
+}
+
+/// Prints `value` and everything below it, one value per line starting with `prefix`.
+fn dump_value(value: &Value, path: &ValuePath, prefix: &str) {
+    println!("{}{} = {}", prefix, path, value::describe(value));
+
+    match value {
+        Value::AMF3(inner) => dump_children(inner, path, prefix),
+        value => dump_children(value, path, prefix),
+    }
+}
+
+fn dump_children(value: &Value, path: &ValuePath, prefix: &str) {
+    match value {
+        Value::Object(elements, _) => dump_elements(elements, path, prefix),
+        Value::ECMAArray(dense, elements, _) => {
+            dump_values(dense, path, prefix);
+            dump_elements(elements, path, prefix);
+        }
+        Value::StrictArray(values) | Value::VectorObject(values, _, _) => dump_values(values, path, prefix),
+        Value::VectorInt(items, _) => {
+            for (index, item) in items.iter().enumerate() {
+                println!("{}{} = Integer {}", prefix, path.index(index), item);
+            }
+        }
+        Value::VectorUInt(items, _) => {
+            for (index, item) in items.iter().enumerate() {
+                println!("{}{} = UInt {}", prefix, path.index(index), item);
+            }
+        }
+        Value::VectorDouble(items, _) => {
+            for (index, item) in items.iter().enumerate() {
+                println!("{}{} = Number {}", prefix, path.index(index), item);
+            }
+        }
+        Value::Dictionary(pairs, _) => {
+            for (index, (key, value)) in pairs.iter().enumerate() {
+                let entry_path = path.index(index);
+                dump_value(key, &entry_path.child("<key>"), prefix);
+                dump_value(value, &entry_path, prefix);
+            }
+        }
+        Value::Custom(custom_elements, elements, _) => {
+            dump_elements(custom_elements, path, prefix);
+            dump_elements(elements, path, prefix);
+        }
+        _ => {}
+    }
+}
+
+fn dump_elements(elements: &[Element], path: &ValuePath, prefix: &str) {
+    for element in elements {
+        dump_value(element.value(), &path.child(&element.name), prefix);
+    }
+}
+
+fn dump_values(values: &[Rc<Value>], path: &ValuePath, prefix: &str) {
+    for (index, value) in values.iter().enumerate() {
+        dump_value(value, &path.index(index), prefix);
+    }
+}
+
+fn get(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, _) = parse_args(args, &[])?;
+    let [file, path] = expect_arguments(&positional, "<file> <path>")?;
+    let path = parse_path(path)?;
+    let document = open(file, classes)?;
+
+    let value = path
+        .get(&document.lso.body)
+        .ok_or_else(|| Failure::new(format!("nothing found at {}", path)))?;
+
+    match value::format_scalar(value) {
+        Some(text) => println!("{}", text),
+        None => dump_value(value, &path, ""),
+    }
+
+    Ok(())
+}
+
+fn query(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, _) = parse_args(args, &[])?;
+    let [file, text] = expect_arguments(&positional, "<file> <query>")?;
+    let query: Query = text
+        .parse()
+        .map_err(|error| Failure::usage(format!("`{}` is not a valid query: {}", text, error)))?;
+    let document = open(file, classes)?;
+
+    let matches = query.run(&document.lso.body);
+    for found in &matches {
+        println!("{} = {}", found.path, value::describe(&found.value));
+    }
+
+    if matches.is_empty() {
+        return Err(Failure::silent(EXIT_NO_MATCH));
+    }
+
+    Ok(())
+}
+
+fn set(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, options) = parse_args(args, &[&["--type"], &["-o", "--output"]])?;
+    let [file, path, text] = expect_arguments(&positional, "<file> <path> <value>")?;
+    let path = parse_path(path)?;
+    let mut document = open(file, classes)?;
+
+    let current = path
+        .get(&document.lso.body)
+        .ok_or_else(|| Failure::new(format!("nothing found at {}", path)))?;
+
+    let value = match options[0] {
+        Some(type_name) => parse_typed(type_name, text)?,
+        None => parse_like(current, text)?,
+    };
+
+    document
+        .replace(&path, value)
+        .map_err(|error| Failure::new(error.to_string()))?;
+
+    let result = match options[1] {
+        Some(output) => document.save_as(PathBuf::from(output)),
+        None => document.save(),
+    };
+    result.map_err(|error| Failure::new(format!("failed to save: {}", error)))
+}
+
+/// Reads `text` as a value of the same type as `current`.
+fn parse_like(current: &Value, text: &str) -> Result<Value, Failure> {
+    let invalid = || Failure::new(format!("`{}` is not a valid {}", text, value::type_name(current)));
+
+    let value = match current {
+        Value::Number(_) => Value::Number(text.parse().map_err(|_| invalid())?),
+        Value::Integer(_) => {
+            let integer = text.parse().map_err(|_| invalid())?;
+            if !(value::INTEGER_MIN..=value::INTEGER_MAX).contains(&integer) {
+                return Err(invalid());
+            }
+            Value::Integer(integer)
+        }
+        Value::Bool(_) => Value::Bool(text.parse().map_err(|_| invalid())?),
+        Value::String(_) => Value::String(text.to_string()),
+        Value::XML(_, is_string) => Value::XML(text.to_string(), *is_string),
+        // Dates are given either as ISO-8601 or as milliseconds since the epoch
+        Value::Date(_, timezone) => {
+            let millis = match text.parse::<DateTime>() {
+                Ok(date_time) => date_time.to_millis(),
+                Err(_) => text.parse().map_err(|_| invalid())?,
+            };
+            Value::Date(millis, *timezone)
+        }
+        Value::ByteArray(_) => Value::ByteArray(parse_hex(text).ok_or_else(invalid)?),
+        Value::AMF3(inner) => Value::AMF3(Rc::new(parse_like(inner, text)?)),
+        Value::Null | Value::Undefined | Value::Unsupported => {
+            return Err(Failure::new(format!(
+                "{} has no type to read `{}` as, use --type",
+                value::type_name(current),
+                text
+            )))
+        }
+        _ => {
+            return Err(Failure::new(format!(
+                "{} values can't be set from text",
+                value::type_name(current)
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+/// Reads `text` as a value of the type called `type_name`.
+fn parse_typed(type_name: &str, text: &str) -> Result<Value, Failure> {
+    let example = match type_name {
+        "number" => Value::Number(0.0),
+        "integer" => Value::Integer(0),
+        "bool" => Value::Bool(false),
+        "string" => Value::String(String::new()),
+        "null" => return Ok(Value::Null),
+        "undefined" => return Ok(Value::Undefined),
+        _ => return Err(Failure::usage(format!("unknown type `{}`", type_name))),
+    };
+
+    parse_like(&example, text)
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn version_name(version: AMFVersion) -> &'static str {
+    match version {
+        AMFVersion::AMF0 => "AMF0",
+        AMFVersion::AMF3 => "AMF3",
+    }
+}
+
+fn convert(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, options) = parse_args(args, &[&["--to"]])?;
+    let [input, output] = expect_arguments(&positional, "<input> <output>")?;
+
+    let version = match options[0].map(str::to_ascii_lowercase).as_deref() {
+        Some("amf0") => AMFVersion::AMF0,
+        Some("amf3") => AMFVersion::AMF3,
+        Some(version) => return Err(Failure::usage(format!("unknown version `{}`", version))),
+        None => return Err(Failure::usage("expected --to amf0 or --to amf3")),
+    };
+
+    let document = open(input, Rc::clone(&classes))?;
+    let lso = convert::convert(&document.lso, version)
+        .map_err(|error| Failure::new(format!("can't convert {}: {}", input, error)))?;
+
+    SolDocument::new(lso, None, classes)
+        .save_as(PathBuf::from(output))
+        .map_err(|error| Failure::new(format!("failed to save {}: {}", output, error)))
+}
+
+fn validate(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, _) = parse_args(args, &[])?;
+    let [file] = expect_arguments(&positional, "<file>")?;
+
+    let data = std::fs::read(file).map_err(|error| Failure::new(format!("failed to read {}: {}", file, error)))?;
+    let mut document = SolDocument::from_bytes(&data, classes)
+        .map_err(|error| Failure::new(format!("failed to open {}: {}", file, error)))?;
+
+    let declared_length = document.lso.header.length;
+    document
+        .validate()
+        .map_err(|error| Failure::new(format!("{} is invalid, {}", file, error)))?;
+
+    let actual_length = data.len() - document::LENGTH_PREFIX;
+    if declared_length as usize != actual_length {
+        println!(
+            "warning: the header gives a length of {} bytes but the file has {}",
+            declared_length, actual_length
+        );
+    }
+
+    println!("{} is valid", file);
+    Ok(())
+}
+
+fn diff(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, _) = parse_args(args, &[])?;
+    let [old_file, new_file] = expect_arguments(&positional, "<old> <new>")?;
+    let old = open(old_file, Rc::clone(&classes))?;
+    let new = open(new_file, classes)?;
+
+    let old_header = &old.lso.header;
+    let new_header = &new.lso.header;
+    let header_changed =
+        old_header.name != new_header.name || old_header.format_version != new_header.format_version;
+    let differences = diff::diff(&old.lso.body, &new.lso.body);
+
+    if !header_changed && differences.is_empty() {
+        return Ok(());
+    }
+
+    println!("--- {}", old_file);
+    println!("+++ {}", new_file);
+
+    if header_changed {
+        println!("@@ header @@");
+        println!("-# {:?}, {}", old_header.name, version_name(old_header.format_version));
+        println!("+# {:?}, {}", new_header.name, version_name(new_header.format_version));
+    }
+
+    for difference in &differences {
+        let path = &difference.path;
+
+        match &difference.change {
+            Change::Added(value) => {
+                println!("@@ {} added @@", path);
+                dump_value(value, path, "+");
+            }
+            Change::Removed(value) => {
+                println!("@@ {} removed @@", path);
+                dump_value(value, path, "-");
+            }
+            Change::Changed { old, new } => {
+                if difference.change.is_type_change() {
+                    println!(
+                        "@@ {} changed type from {} to {} @@",
+                        path,
+                        value::type_name(old),
+                        value::type_name(new)
+                    );
+                } else {
+                    println!("@@ {} changed @@", path);
+                }
+                dump_value(old, path, "-");
+                dump_value(new, path, "+");
+            }
+        }
+    }
+
+    Err(Failure::silent(EXIT_DIFFERENT))
+}
+
+/// Reads the `--format` option, falling back to the extension of `path`.
+fn parse_format(format: Option<&str>, path: Option<&str>) -> Result<Option<Format>, Failure> {
+    match format {
+        Some(name) => Format::from_name(name)
+            .map(Some)
+            .ok_or_else(|| Failure::usage(format!("unknown format `{}`", name))),
+        None => Ok(path.and_then(|path| Format::from_path(path.as_ref()))),
+    }
+}
+
+fn export(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, options) = parse_args(args, &[&["--format"]])?;
+    let (file, output) = match positional[..] {
+        [file] => (file, None),
+        [file, output] => (file, Some(output)),
+        _ => return Err(Failure::usage("expected <file> [<output>]")),
+    };
+    let format = parse_format(options[0], output)?.unwrap_or(Format::Json);
+
+    let document = open(file, classes)?;
+    let text = format.write(&document.lso);
+
+    match output {
+        Some(output) => std::fs::write(output, text)
+            .map_err(|error| Failure::new(format!("failed to write {}: {}", output, error))),
+        None => {
+            print!("{}", text);
+            if !text.ends_with('\n') {
+                println!();
+            }
+            Ok(())
+        }
+    }
+}
+
+fn import(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, options) = parse_args(args, &[&["--format"]])?;
+    let [input, output] = expect_arguments(&positional, "<input> <output>")?;
+    let format = parse_format(options[0], Some(input))?
+        .ok_or_else(|| Failure::usage(format!("can't tell the format of {}, use --format", input)))?;
+
+    let text = std::fs::read_to_string(input)
+        .map_err(|error| Failure::new(format!("failed to read {}: {}", input, error)))?;
+    let lso = format
+        .read(&text)
+        .map_err(|error| Failure::new(format!("failed to import {}: {}", input, error)))?;
+
+    SolDocument::new(lso, None, classes)
+        .save_as(PathBuf::from(output))
+        .map_err(|error| Failure::new(format!("failed to save {}: {}", output, error)))
+}
+
+fn run_script(args: &[String], classes: Rc<CustomClasses>) -> CommandResult {
+    let (positional, options) = parse_args(args, &[&["-o", "--output"]])?;
+    let [file, script_path] = expect_arguments(&positional, "<file> <script>")?;
+    let script = std::fs::read_to_string(script_path)
+        .map_err(|error| Failure::new(format!("failed to read {}: {}", script_path, error)))?;
+    let mut document = open(file, classes)?;
+
+    let run = script::run(&script, &document.lso.body, document.lso.header.format_version);
+    for line in &run.output {
+        println!("{}", line);
+    }
+    let body = run.result.map_err(|error| Failure::new(format!("script failed: {}", error)))?;
+
+    // Scripts that only read the file leave it untouched
+    let changed = !diff::same_elements(&body, &document.lso.body);
+    document.lso.body = body;
+
+    let result = match options[0] {
+        Some(output) => document.save_as(PathBuf::from(output)),
+        None if changed => document.save(),
+        None => return Ok(()),
+    };
+    result.map_err(|error| Failure::new(format!("failed to save: {}", error)))
+}
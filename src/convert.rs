@@ -0,0 +1,141 @@
+//! Conversion of documents between AMF0 and AMF3.
+
+use std::fmt;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Attribute, ClassDefinition, Element, Lso, Value};
+
+use crate::path::ValuePath;
+use crate::value;
+
+/// A value with no equivalent in the target version.
+#[derive(Debug, PartialEq)]
+pub struct ConvertError {
+    pub path: ValuePath,
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {} has no AMF0 equivalent", self.type_name, self.path)
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Rewrites `lso` so it can be written as `version`.
+///
+/// AMF0 has no integers, they become numbers. Objects holding AMF3 only values are kept as AMF3
+/// inside the AMF0 file, other AMF3 only values can't be converted.
+pub fn convert(lso: &Lso, version: AMFVersion) -> Result<Lso, ConvertError> {
+    let body = match version {
+        AMFVersion::AMF0 => convert_elements(&lso.body, &ValuePath::default(), to_amf0)?,
+        AMFVersion::AMF3 => convert_elements(&lso.body, &ValuePath::default(), to_amf3)?,
+    };
+
+    Ok(Lso::new(body, &lso.header.name, version))
+}
+
+type ConvertFn = fn(&Value, &ValuePath) -> Result<Value, ConvertError>;
+
+fn convert_elements(
+    elements: &[Element],
+    path: &ValuePath,
+    convert: ConvertFn,
+) -> Result<Vec<Element>, ConvertError> {
+    elements
+        .iter()
+        .map(|element| {
+            let value = convert(element.value(), &path.child(&element.name))?;
+            Ok(Element::new(element.name.clone(), value))
+        })
+        .collect()
+}
+
+fn convert_values(
+    values: &[Rc<Value>],
+    path: &ValuePath,
+    convert: ConvertFn,
+) -> Result<Vec<Rc<Value>>, ConvertError> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| convert(value, &path.index(index)).map(Rc::new))
+        .collect()
+}
+
+fn to_amf0(value: &Value, path: &ValuePath) -> Result<Value, ConvertError> {
+    let converted = match value {
+        Value::Integer(integer) => Value::Number(*integer as f64),
+        Value::Object(elements, class_definition) => {
+            match convert_elements(elements, path, to_amf0) {
+                // Only typed objects keep their class name in AMF0
+                Ok(elements) => Value::Object(
+                    elements,
+                    class_definition
+                        .as_ref()
+                        .filter(|class_definition| !class_definition.name.is_empty())
+                        .map(|class_definition| ClassDefinition::default_with_name(class_definition.name.clone())),
+                ),
+                Err(_) => Value::AMF3(Rc::new(value.clone())),
+            }
+        }
+        // The dense part becomes named entries, as AMF0 only has the associative part
+        Value::ECMAArray(dense, elements, _) => {
+            let dense = dense
+                .iter()
+                .enumerate()
+                .map(|(index, value)| Element { name: index.to_string(), value: Rc::clone(value) })
+                .collect::<Vec<_>>();
+            let elements = convert_elements(&[dense, elements.clone()].concat(), path, to_amf0)?;
+            let length = elements.len() as u32;
+
+            Value::ECMAArray(Vec::new(), elements, length)
+        }
+        Value::StrictArray(values) => Value::StrictArray(convert_values(values, path, to_amf0)?),
+        Value::AMF3(_) => value.clone(),
+        Value::ByteArray(_)
+        | Value::VectorInt(_, _)
+        | Value::VectorUInt(_, _)
+        | Value::VectorDouble(_, _)
+        | Value::VectorObject(_, _, _)
+        | Value::Dictionary(_, _)
+        | Value::Custom(_, _, _) => {
+            return Err(ConvertError {
+                path: path.clone(),
+                type_name: value::type_name(value),
+            })
+        }
+        _ => value.clone(),
+    };
+
+    Ok(converted)
+}
+
+fn to_amf3(value: &Value, path: &ValuePath) -> Result<Value, ConvertError> {
+    let converted = match value {
+        // AMF0 objects have no traits, their properties become dynamic ones
+        Value::Object(elements, class_definition) => Value::Object(
+            convert_elements(elements, path, to_amf3)?,
+            Some(ClassDefinition {
+                name: class_definition
+                    .as_ref()
+                    .map(|class_definition| class_definition.name.clone())
+                    .unwrap_or_default(),
+                attributes: Attribute::Dynamic.into(),
+                static_properties: Vec::new(),
+            }),
+        ),
+        Value::ECMAArray(dense, elements, length) => Value::ECMAArray(
+            convert_values(dense, path, to_amf3)?,
+            convert_elements(elements, path, to_amf3)?,
+            *length,
+        ),
+        Value::StrictArray(values) => Value::StrictArray(convert_values(values, path, to_amf3)?),
+        Value::AMF3(inner) => inner.as_ref().clone(),
+        Value::Unsupported => Value::Undefined,
+        _ => value.clone(),
+    };
+
+    Ok(converted)
+}
@@ -0,0 +1,183 @@
+//! Support for externalizable AMF3 objects (`Value::Custom`).
+//!
+//! Externalizable classes write their own data with no length in front of it, so reading them
+//! needs a decoder for the class. Decoders and encoders are kept in a registry keyed by class
+//! name, which starts with the standard Flex classes.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cookie_factory::gen;
+use flash_lso::amf3::custom_encoder::{CustomEncoder, ExternalDecoderFn};
+use flash_lso::amf3::read::AMF3Decoder;
+use flash_lso::amf3::write::AMF3Encoder;
+use flash_lso::errors::Error;
+use flash_lso::extra::flex;
+use flash_lso::read::Reader;
+use flash_lso::types::{Attribute, ClassDefinition, Element, Lso, Value};
+use flash_lso::write::Writer;
+use nom::error::ErrorKind;
+use nom::IResult;
+
+use crate::value;
+
+/// Name of the element holding the bytes of a class that has no decoder.
+pub const RAW_ELEMENT: &str = "raw";
+
+/// Decoders and encoders for externalizable classes, keyed by class name.
+pub struct CustomClasses {
+    decoders: HashMap<String, ExternalDecoderFn>,
+    encoders: HashMap<String, Rc<dyn CustomEncoder>>,
+}
+
+impl Default for CustomClasses {
+    /// A registry with the Flex classes such as `flex.messaging.io.ArrayCollection`.
+    fn default() -> Self {
+        let mut decoder = AMF3Decoder::default();
+        flex::read::register_decoders(&mut decoder);
+
+        let mut encoder = AMF3Encoder::default();
+        flex::write::register_encoders(&mut encoder);
+
+        Self {
+            decoders: decoder.external_decoders,
+            encoders: encoder
+                .external_encoders
+                .into_iter()
+                .map(|(name, encoder)| (name, Rc::from(encoder)))
+                .collect(),
+        }
+    }
+}
+
+impl CustomClasses {
+    pub fn has_decoder(&self, class_name: &str) -> bool {
+        self.decoders.contains_key(class_name)
+    }
+
+    /// An AMF3 decoder that can read every registered class.
+    pub fn decoder(&self) -> AMF3Decoder {
+        AMF3Decoder {
+            external_decoders: self.decoders.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Reads a SOL file.
+    ///
+    /// Objects of classes without a decoder are kept as the raw bytes up to the end of the file,
+    /// which is only correct if the object is the last value in the file. Otherwise reading fails
+    /// with the error from before the raw bytes were tried.
+    pub fn parse<'a>(&self, data: &'a [u8]) -> Result<Lso, nom::Err<Error<'a>>> {
+        let mut raw_classes: Vec<String> = Vec::new();
+        let mut first_error = None;
+
+        loop {
+            let mut reader = Reader {
+                amf3_decoder: self.decoder(),
+            };
+            for class_name in &raw_classes {
+                let raw: ExternalDecoderFn = Rc::new(Box::new(decode_raw));
+                reader.amf3_decoder.external_decoders.insert(class_name.clone(), raw);
+            }
+
+            let error = match reader.parse(data) {
+                Ok((_, lso)) => return Ok(lso),
+                Err(error) => first_error.get_or_insert(error).clone(),
+            };
+
+            // Reading stops at the first object without a decoder, whose class was just added
+            let unknown = reader.amf3_decoder.trait_reference_table.iter().find(|class| {
+                class.attributes.contains(Attribute::External)
+                    && !self.has_decoder(&class.name)
+                    && !raw_classes.contains(&class.name)
+            });
+
+            match unknown {
+                Some(class) => raw_classes.push(class.name.clone()),
+                None => return Err(error),
+            }
+        }
+    }
+
+    /// Writes a SOL file, objects read without a decoder are written back from their raw bytes.
+    pub fn write(&self, lso: &Lso) -> Vec<u8> {
+        let mut writer = Writer {
+            amf3_encoder: self.encoder(&lso.body),
+        };
+
+        // Every class in the body has an encoder so writing to a Vec can't fail
+        let (bytes, _) = gen(writer.write_full(lso), Vec::new()).unwrap();
+        bytes
+    }
+
+    /// An AMF3 encoder for every registered class and the unregistered ones used in `elements`.
+    fn encoder(&self, elements: &[Element]) -> AMF3Encoder {
+        let mut encoder = AMF3Encoder::default();
+
+        for element in elements {
+            value::walk(element.value(), &mut |value| {
+                if let Value::Custom(_, _, Some(ClassDefinition { name, .. })) = value {
+                    encoder
+                        .external_encoders
+                        .entry(name.clone())
+                        .or_insert_with(|| Box::new(RawEncoder));
+                }
+            });
+        }
+
+        for (class_name, class_encoder) in &self.encoders {
+            encoder
+                .external_encoders
+                .insert(class_name.clone(), Box::new(SharedEncoder(Rc::clone(class_encoder))));
+        }
+
+        encoder
+    }
+}
+
+/// Keeps everything up to the final padding byte of the file as the object's data.
+fn decode_raw<'a>(i: &'a [u8], _decoder: &mut AMF3Decoder) -> IResult<&'a [u8], Vec<Element>, Error<'a>> {
+    match i.split_last() {
+        Some((_, payload)) => Ok((
+            &i[payload.len()..],
+            vec![Element::new(RAW_ELEMENT, Value::ByteArray(payload.to_vec()))],
+        )),
+        None => Err(nom::Err::Error(Error::Nom(i, ErrorKind::Eof))),
+    }
+}
+
+/// Writes back the bytes kept by `decode_raw`.
+struct RawEncoder;
+
+impl CustomEncoder for RawEncoder {
+    fn encode(
+        &self,
+        elements: &[Element],
+        _class_def: &Option<ClassDefinition>,
+        _encoder: &AMF3Encoder,
+    ) -> Vec<u8> {
+        elements
+            .iter()
+            .find(|element| element.name == RAW_ELEMENT)
+            .and_then(|element| match element.value() {
+                Value::ByteArray(bytes) => Some(bytes.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Lets a registered encoder be installed in more than one `AMF3Encoder`.
+struct SharedEncoder(Rc<dyn CustomEncoder>);
+
+impl CustomEncoder for SharedEncoder {
+    fn encode(
+        &self,
+        elements: &[Element],
+        class_def: &Option<ClassDefinition>,
+        encoder: &AMF3Encoder,
+    ) -> Vec<u8> {
+        self.0.encode(elements, class_def, encoder)
+    }
+}
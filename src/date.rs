@@ -0,0 +1,264 @@
+use std::fmt;
+use std::str::FromStr;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// A UTC calendar date and time, as stored by `Value::Date` in milliseconds since the epoch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millisecond: u32,
+}
+
+impl DateTime {
+    /// Splits `millis` since the epoch into calendar fields, fractions of a millisecond are dropped.
+    /// Returns `None` for NaN and infinite values, which Flash uses for invalid dates.
+    pub fn from_millis(millis: f64) -> Option<Self> {
+        if !millis.is_finite() {
+            return None;
+        }
+
+        let millis = millis.floor() as i64;
+        let days = millis.div_euclid(MILLIS_PER_DAY);
+        let time = millis.rem_euclid(MILLIS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days);
+
+        Some(Self {
+            year,
+            month,
+            day,
+            hour: time / 3_600_000,
+            minute: time / 60_000 % 60,
+            second: time / 1000 % 60,
+            millisecond: time % 1000,
+        })
+    }
+
+    pub fn to_millis(self) -> f64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let time = ((self.hour as i64 * 60 + self.minute as i64) * 60 + self.second as i64) * 1000
+            + self.millisecond as i64;
+
+        (days * MILLIS_PER_DAY + time) as f64
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Formats as ISO-8601, e.g. `2021-10-18T13:37:00.000Z`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millisecond
+        )
+    }
+}
+
+/// The error returned when a string isn't a date in the format written by `DateTime`.
+#[derive(Debug, PartialEq)]
+pub struct ParseDateError;
+
+impl fmt::Display for ParseDateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected a date like 2021-10-18T13:37:00.000Z")
+    }
+}
+
+impl std::error::Error for ParseDateError {}
+
+impl FromStr for DateTime {
+    type Err = ParseDateError;
+
+    /// Parses ISO-8601 UTC dates, the milliseconds and the seconds are optional.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_suffix('Z').ok_or(ParseDateError)?;
+        let (date, time) = s.split_once('T').ok_or(ParseDateError)?;
+
+        // A leading minus belongs to the year, not a separator
+        let (sign, date) = match date.strip_prefix('-') {
+            Some(date) => (-1, date),
+            None => (1, date),
+        };
+        let mut date = date.splitn(3, '-');
+        let year: i64 = parse_field(date.next())?;
+        let month = parse_field(date.next())?;
+        let day = parse_field(date.next())?;
+
+        let (time, millisecond) = match time.split_once('.') {
+            Some((time, millis)) if millis.len() == 3 => (time, parse_field(Some(millis))?),
+            Some(_) => return Err(ParseDateError),
+            None => (time, 0),
+        };
+        let mut time = time.splitn(3, ':');
+        let hour = parse_field(time.next())?;
+        let minute = parse_field(time.next())?;
+        let second = time.next().map_or(Ok(0), |second| parse_field(Some(second)))?;
+
+        if !(1..=12).contains(&month)
+            || !(1..=days_in_month(sign * year, month)).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(ParseDateError);
+        }
+
+        Ok(Self {
+            year: sign * year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millisecond,
+        })
+    }
+}
+
+fn parse_field<T: FromStr>(field: Option<&str>) -> Result<T, ParseDateError> {
+    match field {
+        Some(field) if !field.is_empty() && field.bytes().all(|byte| byte.is_ascii_digit()) => {
+            field.parse().map_err(|_| ParseDateError)
+        }
+        _ => Err(ParseDateError),
+    }
+}
+
+pub fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Formats the AMF0 timezone field, a signed offset from UTC in minutes.
+#[cfg(feature = "gui")]
+pub fn format_timezone(timezone: u16) -> String {
+    let offset = timezone as i16 as i32;
+    let sign = if offset < 0 { '-' } else { '+' };
+
+    format!("UTC{}{:02}:{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+}
+
+// The two conversions below are Howard Hinnant's algorithms for the proleptic Gregorian calendar,
+// see http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> DateTime {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn converts_millis_both_ways() {
+        let cases = [
+            (0.0, "1970-01-01T00:00:00.000Z"),
+            (1_634_564_220_123.0, "2021-10-18T13:37:00.123Z"),
+            (951_782_400_000.0, "2000-02-29T00:00:00.000Z"),
+            (-1.0, "1969-12-31T23:59:59.999Z"),
+            (-62_167_219_200_000.0, "0000-01-01T00:00:00.000Z"),
+        ];
+
+        for &(millis, text) in &cases {
+            let date_time = DateTime::from_millis(millis).unwrap();
+            assert_eq!(date_time.to_string(), text);
+            assert_eq!(date_time.to_millis(), millis);
+        }
+    }
+
+    #[test]
+    fn parses_what_display_writes() {
+        for &millis in &[0.0, 1_634_564_220_123.0, -1.0, -62_167_219_200_000.0] {
+            let date_time = DateTime::from_millis(millis).unwrap();
+            assert_eq!(date(&date_time.to_string()), date_time);
+        }
+    }
+
+    #[test]
+    fn parses_optional_seconds_and_millis() {
+        assert_eq!(date("2021-10-18T13:37Z"), date("2021-10-18T13:37:00.000Z"));
+        assert_eq!(date("2021-10-18T13:37:05Z"), date("2021-10-18T13:37:05.000Z"));
+    }
+
+    #[test]
+    fn rejects_invalid_text() {
+        for text in &[
+            "",
+            "2021-10-18T13:37:00",
+            "2021-10-18 13:37:00Z",
+            "2021-13-01T00:00Z",
+            "2021-02-29T00:00Z",
+            "1900-02-29T00:00Z",
+            "2021-10-18T24:00Z",
+            "2021-10-18T13:37:00.1Z",
+            "2021-10-+1T00:00Z",
+        ] {
+            assert_eq!(text.parse::<DateTime>(), Err(ParseDateError), "{}", text);
+        }
+        assert!("2000-02-29T00:00Z".parse::<DateTime>().is_ok());
+    }
+
+    #[test]
+    fn invalid_dates_have_no_fields() {
+        assert_eq!(DateTime::from_millis(f64::NAN), None);
+        assert_eq!(DateTime::from_millis(f64::INFINITY), None);
+        // Fractions of a millisecond are dropped
+        assert_eq!(DateTime::from_millis(1.75).unwrap().millisecond, 1);
+    }
+
+    #[test]
+    fn counts_days_of_leap_years() {
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2021, 4), 30);
+        assert_eq!(days_in_month(2021, 12), 31);
+    }
+
+    #[test]
+    #[cfg(feature = "gui")]
+    fn formats_timezones() {
+        assert_eq!(format_timezone(0), "UTC+00:00");
+        assert_eq!(format_timezone(90), "UTC+01:30");
+        assert_eq!(format_timezone(-300i16 as u16), "UTC-05:00");
+    }
+}
@@ -0,0 +1,211 @@
+//! Structural comparison of two documents.
+//!
+//! Elements are matched by name and array items by index. Containers of the same kind are
+//! compared child by child, anything else is compared as a whole.
+
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Value};
+
+use crate::path::ValuePath;
+use crate::value;
+
+/// How the value at a path differs between the old and the new document.
+#[derive(Clone, Debug)]
+pub enum Change {
+    Added(Value),
+    Removed(Value),
+    Changed { old: Value, new: Value },
+}
+
+impl Change {
+    /// Whether the value was replaced by one of another type.
+    pub fn is_type_change(&self) -> bool {
+        match self {
+            Change::Changed { old, new } => value::type_name(old) != value::type_name(new),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Difference {
+    pub path: ValuePath,
+    pub change: Change,
+}
+
+/// The differences between the bodies `old` and `new`, in the order of `old` with the values
+/// only in `new` after the ones they follow.
+pub fn diff(old: &[Element], new: &[Element]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_elements(old, new, &ValuePath::default(), &mut differences);
+    differences
+}
+
+/// The differences between `old` and `new`, two versions of the value at `path`.
+pub fn diff_value(old: &Value, new: &Value, path: &ValuePath) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_values(old, new, path, &mut differences);
+    differences
+}
+
+/// Pairs elements with the same name. Repeated names are paired in the order they appear.
+fn diff_elements(old: &[Element], new: &[Element], path: &ValuePath, differences: &mut Vec<Difference>) {
+    let mut matched = vec![false; new.len()];
+
+    for element in old {
+        let path = path.child(&element.name);
+        let counterpart = new
+            .iter()
+            .enumerate()
+            .position(|(index, other)| !matched[index] && other.name == element.name);
+
+        match counterpart {
+            Some(index) => {
+                matched[index] = true;
+                diff_values(element.value(), new[index].value(), &path, differences);
+            }
+            None => differences.push(Difference {
+                path,
+                change: Change::Removed(element.value().clone()),
+            }),
+        }
+    }
+
+    for (element, _) in new.iter().zip(matched).filter(|(_, matched)| !matched) {
+        differences.push(Difference {
+            path: path.child(&element.name),
+            change: Change::Added(element.value().clone()),
+        });
+    }
+}
+
+fn diff_items(old: &[Rc<Value>], new: &[Rc<Value>], path: &ValuePath, differences: &mut Vec<Difference>) {
+    for (index, (old, new)) in old.iter().zip(new).enumerate() {
+        diff_values(old, new, &path.index(index), differences);
+    }
+    for (index, value) in old.iter().enumerate().skip(new.len()) {
+        differences.push(Difference {
+            path: path.index(index),
+            change: Change::Removed(value.as_ref().clone()),
+        });
+    }
+    for (index, value) in new.iter().enumerate().skip(old.len()) {
+        differences.push(Difference {
+            path: path.index(index),
+            change: Change::Added(value.as_ref().clone()),
+        });
+    }
+}
+
+fn diff_values(old: &Value, new: &Value, path: &ValuePath, differences: &mut Vec<Difference>) {
+    match (old, new) {
+        // Paths see through AMF3 values, so they are compared by what they hold
+        (Value::AMF3(old), Value::AMF3(new)) => diff_values(old, new, path, differences),
+        (Value::Object(old, old_class), Value::Object(new, new_class)) if old_class == new_class => {
+            diff_elements(old, new, path, differences)
+        }
+        (
+            Value::ECMAArray(old_dense, old_elements, old_length),
+            Value::ECMAArray(new_dense, new_elements, new_length),
+        ) => {
+            let count = differences.len();
+            diff_items(old_dense, new_dense, path, differences);
+            diff_elements(old_elements, new_elements, path, differences);
+
+            // A changed length is only worth reporting if nothing else explains it
+            if old_length != new_length && differences.len() == count {
+                differences.push(Difference {
+                    path: path.clone(),
+                    change: Change::Changed {
+                        old: old.clone(),
+                        new: new.clone(),
+                    },
+                });
+            }
+        }
+        (Value::StrictArray(old), Value::StrictArray(new)) => diff_items(old, new, path, differences),
+        (Value::VectorObject(old, old_type, old_fixed), Value::VectorObject(new, new_type, new_fixed))
+            if old_type == new_type && old_fixed == new_fixed =>
+        {
+            diff_items(old, new, path, differences)
+        }
+        (Value::Dictionary(old, old_weak), Value::Dictionary(new, new_weak))
+            if old_weak == new_weak
+                && old.len() == new.len()
+                && old.iter().zip(new).all(|((old, _), (new, _))| same(old, new)) =>
+        {
+            for (index, ((_, old), (_, new))) in old.iter().zip(new).enumerate() {
+                diff_values(old, new, &path.index(index), differences);
+            }
+        }
+        (Value::Custom(old_custom, old, old_class), Value::Custom(new_custom, new, new_class))
+            if old_class == new_class =>
+        {
+            diff_elements(old_custom, new_custom, path, differences);
+            diff_elements(old, new, path, differences);
+        }
+        (old, new) if !same(old, new) => differences.push(Difference {
+            path: path.clone(),
+            change: Change::Changed {
+                old: old.clone(),
+                new: new.clone(),
+            },
+        }),
+        _ => {}
+    }
+}
+
+/// Whether two values are equal, counting NaN as equal to itself so unchanged invalid dates and
+/// numbers aren't reported.
+pub fn same(old: &Value, new: &Value) -> bool {
+    let same_number = |old: f64, new: f64| old == new || (old.is_nan() && new.is_nan());
+
+    match (old, new) {
+        (Value::Number(old), Value::Number(new)) => same_number(*old, *new),
+        (Value::Date(old, old_timezone), Value::Date(new, new_timezone)) => {
+            same_number(*old, *new) && old_timezone == new_timezone
+        }
+        (Value::VectorDouble(old, old_fixed), Value::VectorDouble(new, new_fixed)) => {
+            old_fixed == new_fixed
+                && old.len() == new.len()
+                && old.iter().zip(new).all(|(old, new)| same_number(*old, *new))
+        }
+        (Value::AMF3(old), Value::AMF3(new)) => same(old, new),
+        (Value::Object(old, old_class), Value::Object(new, new_class)) => {
+            old_class == new_class && same_elements(old, new)
+        }
+        (Value::ECMAArray(old_dense, old, old_length), Value::ECMAArray(new_dense, new, new_length)) => {
+            old_length == new_length && same_items(old_dense, new_dense) && same_elements(old, new)
+        }
+        (Value::StrictArray(old), Value::StrictArray(new)) => same_items(old, new),
+        (Value::VectorObject(old, old_type, old_fixed), Value::VectorObject(new, new_type, new_fixed)) => {
+            old_type == new_type && old_fixed == new_fixed && same_items(old, new)
+        }
+        (Value::Dictionary(old, old_weak), Value::Dictionary(new, new_weak)) => {
+            old_weak == new_weak
+                && old.len() == new.len()
+                && old
+                    .iter()
+                    .zip(new)
+                    .all(|((old_key, old), (new_key, new))| same(old_key, new_key) && same(old, new))
+        }
+        (Value::Custom(old_custom, old, old_class), Value::Custom(new_custom, new, new_class)) => {
+            old_class == new_class && same_elements(old_custom, new_custom) && same_elements(old, new)
+        }
+        (old, new) => old == new,
+    }
+}
+
+/// Whether two lists of elements are equal in the same order, comparing values with `same`.
+pub fn same_elements(old: &[Element], new: &[Element]) -> bool {
+    old.len() == new.len()
+        && old
+            .iter()
+            .zip(new)
+            .all(|(old, new)| old.name == new.name && same(old.value(), new.value()))
+}
+
+fn same_items(old: &[Rc<Value>], new: &[Rc<Value>]) -> bool {
+    old.len() == new.len() && old.iter().zip(new).all(|(old, new)| same(old, new))
+}
@@ -0,0 +1,541 @@
+//! SOL files as documents that can be opened, edited and saved without the editor.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use flash_lso::errors::Error;
+use flash_lso::types::{AMFVersion, Element, Header, Lso, Value};
+use flash_lso::write;
+use nom::error::ErrorKind;
+
+use crate::amf0;
+use crate::convert::ConvertError;
+use crate::custom::CustomClasses;
+use crate::diff;
+use crate::history::Edit;
+use crate::path::ValuePath;
+use crate::query;
+use crate::recover;
+use crate::value;
+
+/// Bytes at the start of a SOL file that are not counted by `Header.length`:
+/// the two byte version marker followed by the four byte length itself.
+pub const LENGTH_PREFIX: usize = 6;
+
+/// A SOL file loaded into the editor, along with where it lives on disk.
+pub struct SolDocument {
+    pub lso: Lso,
+    /// `None` until the document has been opened from or saved to a file.
+    pub path: Option<PathBuf>,
+    /// Used to write externalizable objects in the document.
+    pub classes: Rc<CustomClasses>,
+}
+
+impl Default for SolDocument {
+    fn default() -> Self {
+        Self {
+            lso: Lso {
+                header: Header {
+                    length: 0,
+                    name: "Not Loaded".to_string(),
+                    format_version: AMFVersion::AMF0,
+                },
+                body: vec![],
+            },
+            path: None,
+            classes: Rc::new(CustomClasses::default()),
+        }
+    }
+}
+
+impl SolDocument {
+    pub fn new(lso: Lso, path: Option<PathBuf>, classes: Rc<CustomClasses>) -> Self {
+        Self { lso, path, classes }
+    }
+
+    /// Reads and parses the SOL file at `path`.
+    pub fn open(path: impl Into<PathBuf>, classes: Rc<CustomClasses>) -> Result<Self, OpenError> {
+        let path = path.into();
+        let data = fs::read(&path)?;
+        let mut document = Self::from_bytes(&data, classes)?;
+        document.path = Some(path);
+
+        Ok(document)
+    }
+
+    /// Parses the contents of a SOL file. The document has no path until it is saved.
+    pub fn from_bytes(data: &[u8], classes: Rc<CustomClasses>) -> Result<Self, OpenError> {
+        let lso = classes.parse(data).map_err(|error| OpenError::from_parse(data, error))?;
+
+        Ok(Self::new(lso, None, classes))
+    }
+
+    /// Reads the elements of the SOL file at `path` up to the first one that can't be read,
+    /// along with the range of bytes that were dropped. The document has no path so that
+    /// saving it doesn't overwrite the corrupted file.
+    pub fn recover(path: &Path, classes: Rc<CustomClasses>) -> Result<(Self, Range<usize>), OpenError> {
+        let data = fs::read(path)?;
+        let recovered = recover::recover(&data, &classes).ok_or(OpenError::Header)?;
+
+        Ok((Self::new(recovered.lso, None, classes), recovered.unread))
+    }
+
+    /// The value at `path`.
+    pub fn get(&self, path: &ValuePath) -> Option<&Value> {
+        path.get(&self.lso.body)
+    }
+
+    /// The number or integer at `path`.
+    pub fn get_number(&self, path: &ValuePath) -> Option<f64> {
+        match unwrap(self.get(path)?) {
+            Value::Number(number) => Some(*number),
+            Value::Integer(integer) => Some(f64::from(*integer)),
+            _ => None,
+        }
+    }
+
+    /// The string at `path`.
+    pub fn get_str(&self, path: &ValuePath) -> Option<&str> {
+        match unwrap(self.get(path)?) {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, path: &ValuePath) -> Option<bool> {
+        match unwrap(self.get(path)?) {
+            Value::Bool(bool) => Some(*bool),
+            _ => None,
+        }
+    }
+
+    /// The value at `path`, ready to be changed.
+    pub fn get_mut(&mut self, path: &ValuePath) -> Option<&mut Value> {
+        path.get_mut(&mut self.lso.body)
+    }
+
+    /// Replaces the value at `path` with a value of the same type, returns the value it replaced.
+    /// Values stored as AMF3 inside AMF0 files stay that way.
+    pub fn set(&mut self, path: &ValuePath, new: Value) -> Result<Value, EditError> {
+        let old = self.get(path).ok_or_else(|| EditError::NotFound(path.clone()))?;
+        let (expected, found) = (value::type_name(unwrap(old)), value::type_name(unwrap(&new)));
+        if expected != found {
+            return Err(EditError::TypeMismatch {
+                path: path.clone(),
+                expected,
+                found,
+            });
+        }
+
+        self.replace(path, new)
+    }
+
+    /// Replaces the value at `path` with a value of any type, returns the value it replaced.
+    /// Fails for values the document can't hold there, see `store_at`.
+    pub fn replace(&mut self, path: &ValuePath, new: Value) -> Result<Value, EditError> {
+        let old = self.get(path).ok_or_else(|| EditError::NotFound(path.clone()))?.clone();
+        let new = store_at(path, self.version_at(path), Some(&old), new)?;
+
+        path.set(&mut self.lso.body, new);
+        Ok(old)
+    }
+
+    /// The version the value at `path` is encoded with, AMF3 inside values stored as AMF3 in
+    /// AMF0 documents.
+    pub fn version_at(&self, path: &ValuePath) -> AMFVersion {
+        version_at(&self.lso.body, self.lso.header.format_version, path)
+    }
+
+    /// Makes `edit`, returns whether it could be made.
+    pub fn apply(&mut self, edit: &Edit) -> bool {
+        edit.apply(&mut self.lso)
+    }
+
+    /// Checks that the document is read back unchanged after writing it. Writing it updates
+    /// `Header.length`.
+    pub fn validate(&mut self) -> Result<(), ValidationError> {
+        let written = self.serialize().map_err(ValidationError::Unwritable)?;
+        let reread = self.classes.parse(&written).map_err(|_| ValidationError::Unreadable)?;
+
+        if !diff::diff(&self.lso.body, &reread.body).is_empty() {
+            return Err(ValidationError::Changed);
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the document, updating `Header.length` to match the written body. Fails if an
+    /// AMF0 document holds a value AMF0 can't encode.
+    pub fn serialize(&mut self) -> Result<Vec<u8>, ConvertError> {
+        let mut bytes = match self.lso.header.format_version {
+            AMFVersion::AMF0 => {
+                // Only write the header with flash-lso, see `amf0` for why
+                let header = Lso::new_empty(self.lso.header.name.clone(), AMFVersion::AMF0);
+                let mut bytes = write::write_to_bytes(&header);
+                bytes.extend(amf0::write_body(&self.lso.body, &self.classes)?);
+                bytes
+            }
+            AMFVersion::AMF3 => self.classes.write(&self.lso),
+        };
+        let length = (bytes.len() - LENGTH_PREFIX) as u32;
+
+        bytes[2..LENGTH_PREFIX].copy_from_slice(&length.to_be_bytes());
+        self.lso.header.length = length;
+
+        Ok(bytes)
+    }
+
+    /// Writes the document back to the file it was loaded from.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self.path.clone().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "document has no file path")
+        })?;
+
+        self.save_as(path)
+    }
+
+    /// Writes the document to `path`, which becomes the document's path on success.
+    pub fn save_as(&mut self, path: PathBuf) -> io::Result<()> {
+        let bytes = self
+            .serialize()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        write_atomic(&path, &bytes)?;
+        self.path = Some(path);
+
+        Ok(())
+    }
+}
+
+/// Why a SOL file couldn't be opened.
+#[derive(Debug)]
+pub enum OpenError {
+    Io(io::Error),
+    /// The file doesn't start with a SOL header.
+    Header,
+    /// The file ended in the middle of a value.
+    Incomplete,
+    /// A length read from the file is larger than the rest of the file.
+    OutOfBounds,
+    /// flash-lso couldn't read the data starting at byte `offset`.
+    Parse { offset: usize, kind: ErrorKind },
+}
+
+impl OpenError {
+    fn from_parse(data: &[u8], error: nom::Err<Error<'_>>) -> Self {
+        match error {
+            nom::Err::Incomplete(_) => OpenError::Incomplete,
+            nom::Err::Error(error) | nom::Err::Failure(error) => match error {
+                Error::OutOfBounds => OpenError::OutOfBounds,
+                // Parsers only ever advance through `data`, so the rest is always a suffix of it
+                Error::Nom(rest, kind) => OpenError::Parse {
+                    offset: data.len() - rest.len(),
+                    kind,
+                },
+            },
+        }
+    }
+}
+
+impl From<io::Error> for OpenError {
+    fn from(error: io::Error) -> Self {
+        OpenError::Io(error)
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::Io(error) => write!(f, "{}", error),
+            OpenError::Header => f.write_str("not a SOL file, the header couldn't be read"),
+            OpenError::Incomplete => f.write_str("the file ends in the middle of a value"),
+            OpenError::OutOfBounds => f.write_str("a length in the file points past its end"),
+            OpenError::Parse { offset, kind } => write!(
+                f,
+                "invalid data at byte {} (0x{:x}), {} parser failed",
+                offset,
+                offset,
+                kind.description()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpenError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The version the value at `path` in a `body` of `version` is encoded with, AMF3 inside values
+/// stored as AMF3 in AMF0 documents.
+pub fn version_at(body: &[Element], version: AMFVersion, path: &ValuePath) -> AMFVersion {
+    let in_amf3 = path
+        .prefixes()
+        .any(|prefix| prefix != *path && matches!(prefix.get(body), Some(Value::AMF3(_))));
+
+    if in_amf3 {
+        AMFVersion::AMF3
+    } else {
+        version
+    }
+}
+
+/// `new` as it is stored at `path` with `version`, in place of `old` if there is one. Values
+/// stored as AMF3 inside AMF0 files stay that way, see `value::for_version` for the others. Fails
+/// if `new` or any value in it can't be held there, see `value::can_hold`.
+pub fn store_at(path: &ValuePath, version: AMFVersion, old: Option<&Value>, new: Value) -> Result<Value, EditError> {
+    let new = match (old, new) {
+        (Some(Value::AMF3(_)), new @ Value::AMF3(_)) => new,
+        (Some(Value::AMF3(_)), new) => Value::AMF3(Rc::new(new)),
+        // Values are only switched to AMF3 once
+        (_, Value::AMF3(inner)) if version == AMFVersion::AMF3 => {
+            Rc::try_unwrap(inner).unwrap_or_else(|inner| (*inner).clone())
+        }
+        (_, new) => {
+            let type_name = value::type_name(unwrap(&new));
+            value::for_version(new, version).ok_or_else(|| EditError::Unsupported {
+                path: path.clone(),
+                type_name,
+            })?
+        }
+    };
+
+    match unholdable(version, &new, path) {
+        Some(error) => Err(error),
+        None => Ok(new),
+    }
+}
+
+/// The first value at or below `path` that a document of `version` can't hold.
+fn unholdable(version: AMFVersion, value: &Value, path: &ValuePath) -> Option<EditError> {
+    if version == AMFVersion::AMF3 {
+        return None;
+    }
+    if !value::can_hold(version, value) {
+        return Some(EditError::Unsupported {
+            path: path.clone(),
+            type_name: value::type_name(unwrap(value)),
+        });
+    }
+
+    match value {
+        // Anything goes inside values stored as AMF3
+        Value::AMF3(_) => None,
+        value => query::value_children(value)
+            .into_iter()
+            .find_map(|(segment, child)| unholdable(version, child, &path.join(segment))),
+    }
+}
+
+/// Why a value couldn't be changed.
+#[derive(Debug, PartialEq)]
+pub enum EditError {
+    NotFound(ValuePath),
+    /// The new value has another type than the value it replaces.
+    TypeMismatch {
+        path: ValuePath,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The document can't hold a value of this type at the path.
+    Unsupported { path: ValuePath, type_name: &'static str },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NotFound(path) => write!(f, "nothing found at {}", path),
+            EditError::TypeMismatch { path, expected, found } => {
+                write!(f, "{} is a {}, it can't be set to a {}", path, expected, found)
+            }
+            EditError::Unsupported { path, type_name } => {
+                write!(f, "{} values can't be stored at {} in AMF0 files", type_name, path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Why a document failed validation.
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// The written bytes can't be read.
+    Unreadable,
+    /// The values read back differ from the document.
+    Changed,
+    /// A value can't be written in the document's format.
+    Unwritable(ConvertError),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Unreadable => f.write_str("it can't be read back after writing it"),
+            ValidationError::Changed => f.write_str("it changes when it is written back"),
+            ValidationError::Unwritable(error) => write!(f, "it can't be written, {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+fn unwrap(value: &Value) -> &Value {
+    match value {
+        Value::AMF3(inner) => inner,
+        value => value,
+    }
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path`,
+/// so an interrupted save leaves the previous file untouched.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp_path = OsString::from(path.as_os_str());
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flash_lso::read::Reader;
+
+    fn document(version: AMFVersion, body: Vec<Element>) -> SolDocument {
+        SolDocument::new(Lso::new(body, "test", version), None, Rc::new(CustomClasses::default()))
+    }
+
+    fn path(path: &str) -> ValuePath {
+        path.parse().unwrap()
+    }
+
+    fn amf3_object(elements: Vec<Element>) -> Value {
+        Value::AMF3(Rc::new(Value::Object(elements, value::anonymous_class(AMFVersion::AMF3))))
+    }
+
+    #[test]
+    fn save_as_replaces_the_file_and_updates_the_length() {
+        let path = std::env::temp_dir().join(format!("sol-editor-save-{}.sol", std::process::id()));
+        fs::write(&path, b"previous contents").unwrap();
+        let mut document = document(AMFVersion::AMF0, vec![Element::new("a", Value::Number(1.0))]);
+
+        document.save_as(path.clone()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(document.path, Some(path.clone()));
+        assert_eq!(document.lso.header.length as usize, bytes.len() - LENGTH_PREFIX);
+        assert_eq!(Reader::default().parse(&bytes).unwrap().1.body, document.lso.body);
+        // Nothing is left behind next to the file
+        assert!(!path.with_extension("sol.tmp").exists());
+    }
+
+    #[test]
+    fn validate_accepts_what_it_writes() {
+        let mut document = document(
+            AMFVersion::AMF0,
+            vec![
+                Element::new("a", Value::Number(1.0)),
+                Element::new("w", amf3_object(vec![Element::new("count", Value::Integer(5))])),
+            ],
+        );
+
+        assert_eq!(document.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_accepts_nan() {
+        let mut document = document(
+            AMFVersion::AMF0,
+            vec![
+                Element::new("number", Value::Number(f64::NAN)),
+                Element::new("date", Value::Date(f64::NAN, Some(0))),
+            ],
+        );
+
+        assert_eq!(document.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_unwritable_values() {
+        let mut document = document(AMFVersion::AMF0, vec![Element::new("a", Value::Integer(1))]);
+
+        assert!(matches!(document.validate(), Err(ValidationError::Unwritable(_))));
+    }
+
+    #[test]
+    fn set_keeps_the_type() {
+        let mut document = document(AMFVersion::AMF0, vec![Element::new("a", Value::Number(1.0))]);
+
+        assert_eq!(document.set(&path("a"), Value::Number(2.0)), Ok(Value::Number(1.0)));
+        assert_eq!(document.get_number(&path("a")), Some(2.0));
+        assert_eq!(
+            document.set(&path("a"), Value::Bool(true)),
+            Err(EditError::TypeMismatch {
+                path: path("a"),
+                expected: "Number",
+                found: "Bool",
+            })
+        );
+        assert_eq!(document.set(&path("b"), Value::Null), Err(EditError::NotFound(path("b"))));
+    }
+
+    #[test]
+    fn replace_rejects_values_amf0_can_not_hold() {
+        let mut document = document(
+            AMFVersion::AMF0,
+            vec![
+                Element::new("a", Value::Number(1.0)),
+                Element::new("w", amf3_object(vec![Element::new("count", Value::Integer(5))])),
+            ],
+        );
+
+        assert_eq!(
+            document.replace(&path("a"), Value::ByteArray(Vec::new())),
+            Err(EditError::Unsupported {
+                path: path("a"),
+                type_name: "ByteArray",
+            })
+        );
+        // AMF0 has no integers
+        document.replace(&path("a"), Value::Integer(5)).unwrap();
+        assert_eq!(document.get(&path("a")), Some(&Value::Number(5.0)));
+        assert!(document.replace(&path("w"), Value::ByteArray(Vec::new())).is_err());
+
+        // Values inside AMF3 objects are written as AMF3
+        assert_eq!(document.version_at(&path("w.count")), AMFVersion::AMF3);
+        document.replace(&path("w.count"), Value::ByteArray(vec![1])).unwrap();
+        assert_eq!(document.get(&path("w.count")), Some(&Value::ByteArray(vec![1])));
+        assert_eq!(document.validate(), Ok(()));
+    }
+
+    #[test]
+    fn replace_wraps_amf3_objects() {
+        let mut document = document(AMFVersion::AMF0, vec![Element::new("w", amf3_object(Vec::new()))]);
+        let object = Value::Object(vec![Element::new("i", Value::Integer(1))], value::anonymous_class(AMFVersion::AMF3));
+
+        document.replace(&path("w"), object.clone()).unwrap();
+        assert_eq!(document.get(&path("w")), Some(&Value::AMF3(Rc::new(object))));
+    }
+}
@@ -0,0 +1,443 @@
+//! Undo and redo of edits to a document.
+
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Lso, Value};
+
+use crate::diff::{self, Change, Difference};
+use crate::path::ValuePath;
+
+/// Edits of the same value less than this many seconds apart are undone together, so typing a
+/// word or dragging a number is a single step.
+const COALESCE_SECONDS: f64 = 1.0;
+
+/// A change to a document that can be undone. Elements are addressed by their index in the
+/// properties of the value at `parent`, or in the body for the root path.
+#[derive(Clone, Debug)]
+pub enum Edit {
+    SetValue { path: ValuePath, old: Value, new: Value },
+    Rename { parent: ValuePath, index: usize, old: String, new: String },
+    Insert { parent: ValuePath, index: usize, element: Element },
+    Delete { parent: ValuePath, index: usize, element: Element },
+    Move { parent: ValuePath, from: usize, to: usize },
+    /// Renames the document, the name is the only header field that can be edited.
+    Header { old: String, new: String },
+    /// Replaces every element, as applying a merge does.
+    Body { old: Vec<Element>, new: Vec<Element> },
+    /// Edits made and undone together, as a bulk replace does.
+    Group(Vec<Edit>),
+}
+
+impl Edit {
+    /// The edit replacing `old` with `new` at `path`. If only one value inside them changed the
+    /// edit is narrowed down to it, so that edits of different children aren't coalesced.
+    /// Differences at `path` itself keep the values as given, as the diff sees through the
+    /// `Value::AMF3` wrapping them.
+    pub fn set_value(path: &ValuePath, old: &Value, new: &Value) -> Self {
+        let differences = diff::diff_value(old, new, path);
+        let (path, old, new) = match &differences[..] {
+            [Difference {
+                path: changed,
+                change: Change::Changed { old, new },
+            }] if changed != path => (changed, old, new),
+            _ => (path, old, new),
+        };
+
+        Edit::SetValue {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }
+    }
+
+    /// The edit undoing this one.
+    pub fn inverse(&self) -> Self {
+        match self.clone() {
+            Edit::SetValue { path, old, new } => Edit::SetValue { path, old: new, new: old },
+            Edit::Rename { parent, index, old, new } => Edit::Rename { parent, index, old: new, new: old },
+            Edit::Insert { parent, index, element } => Edit::Delete { parent, index, element },
+            Edit::Delete { parent, index, element } => Edit::Insert { parent, index, element },
+            Edit::Move { parent, from, to } => Edit::Move { parent, from: to, to: from },
+            Edit::Header { old, new } => Edit::Header { old: new, new: old },
+            Edit::Body { old, new } => Edit::Body { old: new, new: old },
+            Edit::Group(edits) => Edit::Group(edits.iter().rev().map(Edit::inverse).collect()),
+        }
+    }
+
+    /// The values this edit changes, to mark as edited, `None` if it changes the whole document.
+    /// Elements that are renamed, inserted or deleted are marked along with their parent, which
+    /// has no row of its own when it is the body.
+    pub fn paths(&self) -> Option<Vec<ValuePath>> {
+        match self {
+            Edit::SetValue { path, .. } => Some(vec![path.clone()]),
+            Edit::Rename { parent, new: name, .. }
+            | Edit::Insert {
+                parent,
+                element: Element { name, .. },
+                ..
+            }
+            | Edit::Delete {
+                parent,
+                element: Element { name, .. },
+                ..
+            } => Some(vec![parent.clone(), parent.child(name.as_str())]),
+            Edit::Move { parent, .. } => Some(vec![parent.clone()]),
+            Edit::Header { .. } | Edit::Body { .. } => None,
+            Edit::Group(edits) => edits.iter().try_fold(Vec::new(), |mut paths, edit| {
+                paths.extend(edit.paths()?);
+                Some(paths)
+            }),
+        }
+    }
+
+    /// Makes the edit, returns whether it could be made.
+    pub fn apply(&self, lso: &mut Lso) -> bool {
+        match self {
+            Edit::Header { new, .. } => {
+                lso.header.name = new.clone();
+                true
+            }
+            Edit::Body { new, .. } => {
+                lso.body = new.clone();
+                true
+            }
+            Edit::Group(edits) => {
+                // The other edits are still made if one of them can't be
+                let mut applied = true;
+                for edit in edits {
+                    applied &= edit.apply(lso);
+                }
+                applied
+            }
+            edit => edit.apply_to_body(&mut lso.body),
+        }
+    }
+
+    /// Makes a value or element edit on `body`, returns whether it could be made. Edits of the
+    /// header or the whole body can't be made this way.
+    pub fn apply_to_body(&self, body: &mut Vec<Element>) -> bool {
+        match self {
+            Edit::SetValue { path, new, .. } => path.set(body, new.clone()),
+            Edit::Rename { parent, .. }
+            | Edit::Insert { parent, .. }
+            | Edit::Delete { parent, .. }
+            | Edit::Move { parent, .. } => {
+                if parent.is_root() {
+                    return self.apply_to_elements(body);
+                }
+
+                match parent.get_mut(body) {
+                    Some(value) => self.apply_to_value(value),
+                    None => false,
+                }
+            }
+            Edit::Header { .. } | Edit::Body { .. } | Edit::Group(_) => false,
+        }
+    }
+
+    /// Makes an element edit on the properties of `value`, keeping the declared length of arrays
+    /// in step with their entries.
+    fn apply_to_value(&self, value: &mut Value) -> bool {
+        match value {
+            Value::AMF3(inner) => self.apply_to_value(Rc::make_mut(inner)),
+            Value::Object(elements, _) | Value::Custom(_, elements, _) => self.apply_to_elements(elements),
+            Value::ECMAArray(_, elements, length) => {
+                let count = elements.len();
+                let applied = self.apply_to_elements(elements);
+                *length = (*length as i64 + elements.len() as i64 - count as i64).max(0) as u32;
+                applied
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes an element edit on the properties it addresses, returns whether it could be made.
+    pub fn apply_to_elements(&self, elements: &mut Vec<Element>) -> bool {
+        match self {
+            Edit::Rename { index, new, .. } => match elements.get_mut(*index) {
+                Some(element) => {
+                    element.name = new.clone();
+                    true
+                }
+                None => false,
+            },
+            Edit::Insert { index, element, .. } if *index <= elements.len() => {
+                elements.insert(*index, element.clone());
+                true
+            }
+            Edit::Delete { index, .. } if *index < elements.len() => {
+                elements.remove(*index);
+                true
+            }
+            Edit::Move { from, to, .. } if *from < elements.len() && *to < elements.len() => {
+                let element = elements.remove(*from);
+                elements.insert(*to, element);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// A short description for the history panel.
+    pub fn description(&self) -> String {
+        let place = |parent: &ValuePath| {
+            if parent.is_root() {
+                String::new()
+            } else {
+                format!(" in {}", parent)
+            }
+        };
+
+        match self {
+            Edit::SetValue { path, .. } => format!("Set {}", path),
+            Edit::Rename { parent, old, new, .. } => format!("Rename {} to {}{}", old, new, place(parent)),
+            Edit::Insert { parent, element, .. } => format!("Add {}{}", element.name, place(parent)),
+            Edit::Delete { parent, element, .. } => format!("Delete {}{}", element.name, place(parent)),
+            Edit::Move { parent, from, to } => format!("Move element {} to {}{}", from, to, place(parent)),
+            Edit::Header { new, .. } => format!("Rename document to {:?}", new),
+            Edit::Body { .. } => "Replace body".to_string(),
+            Edit::Group(edits) => format!("Change {} values", edits.len()),
+        }
+    }
+
+    /// Folds `next`, made right after this edit, into it if both change the same value.
+    fn coalesce(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (Edit::SetValue { path, new, .. }, Edit::SetValue { path: next_path, new: next_new, .. })
+                if path == next_path =>
+            {
+                *new = next_new.clone();
+                true
+            }
+            (Edit::Header { new, .. }, Edit::Header { new: next_new, .. }) => {
+                *new = next_new.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Entry {
+    edit: Edit,
+    /// When the edit was last extended, in seconds.
+    time: f64,
+}
+
+/// The edits made to a document, in order. The ones after `position` were undone and can be
+/// redone until a new edit is made.
+#[derive(Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    position: usize,
+}
+
+impl History {
+    /// Adds `edit`, already made to the document at `time` in seconds.
+    pub fn record(&mut self, edit: Edit, time: f64) {
+        self.entries.truncate(self.position);
+
+        if let Some(last) = self.entries.last_mut() {
+            if time - last.time < COALESCE_SECONDS && last.edit.coalesce(&edit) {
+                last.time = time;
+                return;
+            }
+        }
+
+        self.entries.push(Entry { edit, time });
+        self.position = self.entries.len();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.position < self.entries.len()
+    }
+
+    /// Undoes the last edit, returns the edit made to the document to do it.
+    pub fn undo(&mut self, lso: &mut Lso) -> Option<Edit> {
+        if !self.can_undo() {
+            return None;
+        }
+
+        self.position -= 1;
+        let inverse = self.entries[self.position].edit.inverse();
+        inverse.apply(lso);
+        Some(inverse)
+    }
+
+    /// Redoes the last undone edit, returns it.
+    pub fn redo(&mut self, lso: &mut Lso) -> Option<Edit> {
+        if !self.can_redo() {
+            return None;
+        }
+
+        let edit = self.entries[self.position].edit.clone();
+        edit.apply(lso);
+        self.position += 1;
+        Some(edit)
+    }
+
+    /// Undoes or redoes edits until the first `position` edits are made, returns the edits made
+    /// to the document to get there.
+    pub fn jump(&mut self, position: usize, lso: &mut Lso) -> Vec<Edit> {
+        let mut edits = Vec::new();
+
+        while self.position > position {
+            edits.extend(self.undo(lso));
+        }
+        while self.position < position.min(self.entries.len()) {
+            edits.extend(self.redo(lso));
+        }
+
+        edits
+    }
+
+    /// The number of edits currently made.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn edits(&self) -> impl Iterator<Item = &Edit> {
+        self.entries.iter().map(|entry| &entry.edit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flash_lso::types::AMFVersion;
+
+    fn path(path: &str) -> ValuePath {
+        path.parse().unwrap()
+    }
+
+    fn object(elements: Vec<Element>) -> Value {
+        Value::Object(elements, None)
+    }
+
+    fn lso(body: Vec<Element>) -> Lso {
+        Lso::new(body, "test", AMFVersion::AMF0)
+    }
+
+    fn set_value(edit: &Edit) -> (&ValuePath, &Value, &Value) {
+        match edit {
+            Edit::SetValue { path, old, new } => (path, old, new),
+            edit => panic!("not a SetValue: {:?}", edit),
+        }
+    }
+
+    #[test]
+    fn set_value_narrows_to_the_changed_child() {
+        let old = object(vec![Element::new("x", Value::Number(1.0)), Element::new("y", Value::Number(2.0))]);
+        let new = object(vec![Element::new("x", Value::Number(1.0)), Element::new("y", Value::Number(3.0))]);
+
+        let edit = Edit::set_value(&path("o"), &old, &new);
+        assert_eq!(set_value(&edit), (&path("o.y"), &Value::Number(2.0), &Value::Number(3.0)));
+    }
+
+    #[test]
+    fn set_value_keeps_values_with_several_changes() {
+        let old = object(vec![Element::new("x", Value::Number(1.0)), Element::new("y", Value::Number(2.0))]);
+        let new = object(vec![Element::new("x", Value::Number(0.0)), Element::new("y", Value::Number(3.0))]);
+
+        let edit = Edit::set_value(&path("o"), &old, &new);
+        assert_eq!(set_value(&edit), (&path("o"), &old, &new));
+    }
+
+    #[test]
+    fn set_value_keeps_the_amf3_wrapper() {
+        let old = Value::AMF3(Rc::new(Value::Integer(1)));
+        let new = Value::AMF3(Rc::new(Value::Integer(2)));
+
+        let edit = Edit::set_value(&path("a"), &old, &new);
+        assert_eq!(set_value(&edit), (&path("a"), &old, &new));
+
+        let mut lso = lso(vec![Element::new("a", new.clone())]);
+        edit.inverse().apply(&mut lso);
+        assert_eq!(lso.body[0].value(), &old);
+        edit.apply(&mut lso);
+        assert_eq!(lso.body[0].value(), &new);
+    }
+
+    #[test]
+    fn set_value_narrows_inside_amf3_objects() {
+        let wrap = |count| Value::AMF3(Rc::new(object(vec![Element::new("count", Value::Integer(count))])));
+
+        let edit = Edit::set_value(&path("w"), &wrap(1), &wrap(2));
+        assert_eq!(set_value(&edit), (&path("w.count"), &Value::Integer(1), &Value::Integer(2)));
+
+        let mut lso = lso(vec![Element::new("w", wrap(1))]);
+        edit.apply(&mut lso);
+        assert_eq!(lso.body[0].value(), &wrap(2));
+    }
+
+    #[test]
+    fn paths_include_the_elements_edited_in_the_body() {
+        let edit = Edit::Insert {
+            parent: ValuePath::default(),
+            index: 0,
+            element: Element::new("a", Value::Null),
+        };
+        assert_eq!(edit.paths(), Some(vec![ValuePath::default(), path("a")]));
+
+        let edit = Edit::Rename {
+            parent: path("o"),
+            index: 0,
+            old: "x".to_string(),
+            new: "y".to_string(),
+        };
+        assert_eq!(edit.paths(), Some(vec![path("o"), path("o.y")]));
+    }
+
+    #[test]
+    fn inverse_undoes_edits() {
+        let element = Element::new("a", Value::Null);
+        let mut lso = lso(vec![element.clone(), Element::new("b", Value::Bool(true))]);
+        let original = lso.body.clone();
+
+        let edits = vec![
+            Edit::SetValue { path: path("b"), old: Value::Bool(true), new: Value::Bool(false) },
+            Edit::Rename { parent: ValuePath::default(), index: 0, old: "a".to_string(), new: "c".to_string() },
+            Edit::Move { parent: ValuePath::default(), from: 0, to: 1 },
+            Edit::Delete { parent: ValuePath::default(), index: 1, element: Element::new("c", Value::Null) },
+            Edit::Insert { parent: ValuePath::default(), index: 0, element },
+        ];
+        for edit in &edits {
+            assert!(edit.apply(&mut lso), "{:?}", edit);
+        }
+        assert_eq!(lso.body, vec![Element::new("a", Value::Null), Element::new("b", Value::Bool(false))]);
+
+        let group = Edit::Group(edits);
+        assert!(group.inverse().apply(&mut lso));
+        assert_eq!(lso.body, original);
+    }
+
+    #[test]
+    fn history_coalesces_edits_of_the_same_value() {
+        let mut lso = lso(vec![Element::new("a", Value::Number(0.0)), Element::new("b", Value::Number(0.0))]);
+        let mut history = History::default();
+
+        // The edits of `b` are too far apart to be coalesced
+        for &(name, number, time) in &[("a", 1.0, 0.0), ("a", 2.0, 0.5), ("b", 1.0, 2.0), ("b", 2.0, 4.0)] {
+            let edit = Edit::SetValue { path: path(name), old: Value::Number(number - 1.0), new: Value::Number(number) };
+            edit.apply(&mut lso);
+            history.record(edit, time);
+        }
+        assert_eq!(history.edits().count(), 3);
+
+        history.undo(&mut lso);
+        history.undo(&mut lso);
+        assert_eq!(lso.body[1].value(), &Value::Number(0.0));
+        history.undo(&mut lso);
+        assert_eq!(lso.body[0].value(), &Value::Number(0.0));
+        assert!(!history.can_undo());
+
+        history.jump(3, &mut lso);
+        assert_eq!(lso.body, vec![Element::new("a", Value::Number(2.0)), Element::new("b", Value::Number(2.0))]);
+        assert!(!history.can_redo());
+    }
+}
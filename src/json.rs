@@ -0,0 +1,485 @@
+//! Lossless conversion between `Lso` and JSON.
+//!
+//! A document is an object with the header fields and the body:
+//!
+//! ```json
+//! { "name": "savegame", "version": "AMF3", "body": [<element>, ...] }
+//! ```
+//!
+//! `version` is `"AMF0"` or `"AMF3"`. The header length isn't stored, it is worked out again
+//! when the file is written. An element is `{ "name": "score", "value": <value> }` and every
+//! value is an object whose `type` is the name of the `Value` variant:
+//!
+//! | type           | other fields                                                           |
+//! |----------------|------------------------------------------------------------------------|
+//! | `Number`       | `value`: a number                                                      |
+//! | `Integer`      | `value`: an integer                                                    |
+//! | `Bool`         | `value`: `true` or `false`                                             |
+//! | `String`       | `value`: a string                                                      |
+//! | `Null`, `Undefined`, `Unsupported` | none                                               |
+//! | `Object`       | `class`: a class or `null`, `properties`: elements                     |
+//! | `ECMAArray`    | `dense`: values, `properties`: elements, `length`: the declared length |
+//! | `StrictArray`  | `values`: values                                                       |
+//! | `Date`         | `millis`: a number, `timezone`: minutes as a 16 bit integer or `null`  |
+//! | `XML`          | `value`: a string, `string`: whether it is an `XMLString` in AMF3      |
+//! | `AMF3`         | `value`: the value stored with AMF3 encoding inside an AMF0 file       |
+//! | `ByteArray`    | `hex`: the bytes as lowercase hex                                      |
+//! | `VectorInt`, `VectorUInt`, `VectorDouble` | `values`: numbers, `fixed_length`: a bool   |
+//! | `VectorObject` | `type_name`: a string, `values`: values, `fixed_length`: a bool        |
+//! | `Dictionary`   | `entries`: `{ "key": <value>, "value": <value> }`, `weak_keys`: a bool |
+//! | `Custom`       | `class`: a class, `external`: elements, `properties`: elements         |
+//!
+//! A class is `{ "name": "Point", "attributes": ["Dynamic", "External"], "static_properties":
+//! ["x", "y"] }`. Numbers that JSON can't hold are written as the strings `"NaN"`, `"Infinity"`
+//! and `"-Infinity"`. AMF3 references are written out in full, as they are when the file is
+//! written.
+
+use std::fmt;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Attribute, ClassDefinition, Element, Header, Lso, Value};
+use serde_json::{json, Map, Number};
+
+use crate::path::ValuePath;
+
+type Json = serde_json::Value;
+
+/// Why JSON couldn't be read as a document.
+#[derive(Debug)]
+pub enum JsonError {
+    Syntax(serde_json::Error),
+    /// The JSON doesn't follow the schema at the value at `path`, the root if it is empty.
+    Schema { path: ValuePath, message: String },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax(error) => write!(f, "{}", error),
+            JsonError::Schema { path, message } if *path == ValuePath::default() => f.write_str(message),
+            JsonError::Schema { path, message } => write!(f, "{} at {}", message, path),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// Writes `lso` as pretty printed JSON.
+pub fn to_string(lso: &Lso) -> String {
+    // Serializing a `serde_json::Value` can't fail
+    serde_json::to_string_pretty(&to_json(lso)).unwrap()
+}
+
+/// Reads a document written by `to_string`.
+pub fn from_str(text: &str) -> Result<Lso, JsonError> {
+    let json = serde_json::from_str(text).map_err(JsonError::Syntax)?;
+    from_json(&json)
+}
+
+pub fn to_json(lso: &Lso) -> Json {
+    let version = match lso.header.format_version {
+        AMFVersion::AMF0 => "AMF0",
+        AMFVersion::AMF3 => "AMF3",
+    };
+
+    json!({
+        "name": lso.header.name,
+        "version": version,
+        "body": elements_to_json(&lso.body),
+    })
+}
+
+fn elements_to_json(elements: &[Element]) -> Json {
+    elements
+        .iter()
+        .map(|element| json!({ "name": element.name, "value": value_to_json(element.value()) }))
+        .collect()
+}
+
+fn values_to_json(values: &[Rc<Value>]) -> Json {
+    values.iter().map(|value| value_to_json(value)).collect()
+}
+
+fn number_to_json(number: f64) -> Json {
+    match Number::from_f64(number) {
+        Some(number) => Json::Number(number),
+        None if number.is_nan() => json!("NaN"),
+        None if number > 0.0 => json!("Infinity"),
+        None => json!("-Infinity"),
+    }
+}
+
+fn class_to_json(class_definition: &Option<ClassDefinition>) -> Json {
+    match class_definition {
+        Some(class_definition) => {
+            let attributes: Vec<&str> = class_definition
+                .attributes
+                .iter()
+                .map(|attribute| match attribute {
+                    Attribute::Dynamic => "Dynamic",
+                    Attribute::External => "External",
+                })
+                .collect();
+
+            json!({
+                "name": class_definition.name,
+                "attributes": attributes,
+                "static_properties": class_definition.static_properties,
+            })
+        }
+        None => Json::Null,
+    }
+}
+
+fn value_to_json(value: &Value) -> Json {
+    match value {
+        Value::Number(number) => json!({ "type": "Number", "value": number_to_json(*number) }),
+        Value::Integer(integer) => json!({ "type": "Integer", "value": integer }),
+        Value::Bool(bool) => json!({ "type": "Bool", "value": bool }),
+        Value::String(string) => json!({ "type": "String", "value": string }),
+        Value::Null => json!({ "type": "Null" }),
+        Value::Undefined => json!({ "type": "Undefined" }),
+        Value::Unsupported => json!({ "type": "Unsupported" }),
+        Value::Object(elements, class_definition) => json!({
+            "type": "Object",
+            "class": class_to_json(class_definition),
+            "properties": elements_to_json(elements),
+        }),
+        Value::ECMAArray(dense, elements, length) => json!({
+            "type": "ECMAArray",
+            "dense": values_to_json(dense),
+            "properties": elements_to_json(elements),
+            "length": length,
+        }),
+        Value::StrictArray(values) => json!({ "type": "StrictArray", "values": values_to_json(values) }),
+        Value::Date(millis, timezone) => json!({
+            "type": "Date",
+            "millis": number_to_json(*millis),
+            "timezone": timezone,
+        }),
+        Value::XML(content, string) => json!({ "type": "XML", "value": content, "string": string }),
+        Value::AMF3(inner) => json!({ "type": "AMF3", "value": value_to_json(inner) }),
+        Value::ByteArray(bytes) => {
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            json!({ "type": "ByteArray", "hex": hex })
+        }
+        Value::VectorInt(items, fixed_length) => {
+            json!({ "type": "VectorInt", "values": items, "fixed_length": fixed_length })
+        }
+        Value::VectorUInt(items, fixed_length) => {
+            json!({ "type": "VectorUInt", "values": items, "fixed_length": fixed_length })
+        }
+        Value::VectorDouble(items, fixed_length) => {
+            let values: Json = items.iter().map(|item| number_to_json(*item)).collect();
+            json!({ "type": "VectorDouble", "values": values, "fixed_length": fixed_length })
+        }
+        Value::VectorObject(values, type_name, fixed_length) => json!({
+            "type": "VectorObject",
+            "type_name": type_name,
+            "values": values_to_json(values),
+            "fixed_length": fixed_length,
+        }),
+        Value::Dictionary(pairs, weak_keys) => {
+            let entries: Json = pairs
+                .iter()
+                .map(|(key, value)| json!({ "key": value_to_json(key), "value": value_to_json(value) }))
+                .collect();
+            json!({ "type": "Dictionary", "entries": entries, "weak_keys": weak_keys })
+        }
+        Value::Custom(custom_elements, elements, class_definition) => json!({
+            "type": "Custom",
+            "class": class_to_json(class_definition),
+            "external": elements_to_json(custom_elements),
+            "properties": elements_to_json(elements),
+        }),
+    }
+}
+
+/// Reads the fields of a JSON object, reporting errors at the value at `path`.
+struct Fields<'a> {
+    map: &'a Map<String, Json>,
+    path: &'a ValuePath,
+}
+
+impl<'a> Fields<'a> {
+    fn new(json: &'a Json, path: &'a ValuePath) -> Result<Self, JsonError> {
+        match json.as_object() {
+            Some(map) => Ok(Self { map, path }),
+            None => Err(schema_error(path, "expected an object")),
+        }
+    }
+
+    fn error(&self, message: String) -> JsonError {
+        schema_error(self.path, message)
+    }
+
+    fn get(&self, name: &str) -> Result<&'a Json, JsonError> {
+        self.map
+            .get(name)
+            .ok_or_else(|| self.error(format!("missing field `{}`", name)))
+    }
+
+    fn str(&self, name: &str) -> Result<&'a str, JsonError> {
+        self.get(name)?
+            .as_str()
+            .ok_or_else(|| self.error(format!("`{}` must be a string", name)))
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, JsonError> {
+        self.get(name)?
+            .as_bool()
+            .ok_or_else(|| self.error(format!("`{}` must be a bool", name)))
+    }
+
+    fn array(&self, name: &str) -> Result<&'a Vec<Json>, JsonError> {
+        self.get(name)?
+            .as_array()
+            .ok_or_else(|| self.error(format!("`{}` must be an array", name)))
+    }
+
+    fn integer<T: std::convert::TryFrom<i64>>(&self, name: &str) -> Result<T, JsonError> {
+        integer_from_json(self.get(name)?).ok_or_else(|| self.error(format!("`{}` is not a valid integer", name)))
+    }
+
+    fn number(&self, name: &str) -> Result<f64, JsonError> {
+        number_from_json(self.get(name)?).ok_or_else(|| self.error(format!("`{}` must be a number", name)))
+    }
+
+    fn class(&self, name: &str) -> Result<Option<ClassDefinition>, JsonError> {
+        let json = self.get(name)?;
+        if json.is_null() {
+            return Ok(None);
+        }
+
+        let class = Fields::new(json, self.path)?;
+        let mut class_definition = ClassDefinition::default_with_name(class.str("name")?.to_string());
+
+        for attribute in class.array("attributes")? {
+            class_definition.attributes.insert(match attribute.as_str() {
+                Some("Dynamic") => Attribute::Dynamic,
+                Some("External") => Attribute::External,
+                _ => return Err(self.error(format!("unknown class attribute {}", attribute))),
+            });
+        }
+
+        class_definition.static_properties = class
+            .array("static_properties")?
+            .iter()
+            .map(|property| property.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(|| self.error("static properties must be strings".to_string()))?;
+
+        Ok(Some(class_definition))
+    }
+}
+
+fn schema_error(path: &ValuePath, message: impl Into<String>) -> JsonError {
+    JsonError::Schema {
+        path: path.clone(),
+        message: message.into(),
+    }
+}
+
+fn integer_from_json<T: std::convert::TryFrom<i64>>(json: &Json) -> Option<T> {
+    json.as_i64().and_then(|integer| T::try_from(integer).ok())
+}
+
+fn number_from_json(json: &Json) -> Option<f64> {
+    match json {
+        Json::Number(number) => number.as_f64(),
+        Json::String(string) => match string.as_str() {
+            "NaN" => Some(f64::NAN),
+            "Infinity" => Some(f64::INFINITY),
+            "-Infinity" => Some(f64::NEG_INFINITY),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn from_json(json: &Json) -> Result<Lso, JsonError> {
+    let root = ValuePath::default();
+    let fields = Fields::new(json, &root)?;
+
+    let format_version = match fields.str("version")? {
+        "AMF0" => AMFVersion::AMF0,
+        "AMF3" => AMFVersion::AMF3,
+        version => return Err(fields.error(format!("unknown version `{}`", version))),
+    };
+
+    Ok(Lso {
+        header: Header {
+            length: 0,
+            name: fields.str("name")?.to_string(),
+            format_version,
+        },
+        body: elements_from_json(fields.array("body")?, &root)?,
+    })
+}
+
+fn elements_from_json(elements: &[Json], path: &ValuePath) -> Result<Vec<Element>, JsonError> {
+    elements
+        .iter()
+        .map(|element| {
+            let fields = Fields::new(element, path)?;
+            let name = fields.str("name")?;
+            let value = value_from_json(fields.get("value")?, &path.child(name))?;
+
+            Ok(Element::new(name, value))
+        })
+        .collect()
+}
+
+fn values_from_json(values: &[Json], path: &ValuePath) -> Result<Vec<Rc<Value>>, JsonError> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| value_from_json(value, &path.index(index)).map(Rc::new))
+        .collect()
+}
+
+fn items_from_json<T>(
+    fields: &Fields<'_>,
+    parse: impl Fn(&Json) -> Option<T>,
+) -> Result<Vec<T>, JsonError> {
+    fields
+        .array("values")?
+        .iter()
+        .map(|item| parse(item).ok_or_else(|| fields.error(format!("invalid vector item {}", item))))
+        .collect()
+}
+
+fn value_from_json(json: &Json, path: &ValuePath) -> Result<Value, JsonError> {
+    let fields = Fields::new(json, path)?;
+
+    let value = match fields.str("type")? {
+        "Number" => Value::Number(fields.number("value")?),
+        "Integer" => Value::Integer(fields.integer("value")?),
+        "Bool" => Value::Bool(fields.bool("value")?),
+        "String" => Value::String(fields.str("value")?.to_string()),
+        "Null" => Value::Null,
+        "Undefined" => Value::Undefined,
+        "Unsupported" => Value::Unsupported,
+        "Object" => Value::Object(
+            elements_from_json(fields.array("properties")?, path)?,
+            fields.class("class")?,
+        ),
+        "ECMAArray" => Value::ECMAArray(
+            values_from_json(fields.array("dense")?, path)?,
+            elements_from_json(fields.array("properties")?, path)?,
+            fields.integer("length")?,
+        ),
+        "StrictArray" => Value::StrictArray(values_from_json(fields.array("values")?, path)?),
+        "Date" => {
+            let timezone = match fields.get("timezone")? {
+                Json::Null => None,
+                _ => Some(fields.integer("timezone")?),
+            };
+            Value::Date(fields.number("millis")?, timezone)
+        }
+        "XML" => Value::XML(fields.str("value")?.to_string(), fields.bool("string")?),
+        "AMF3" => Value::AMF3(Rc::new(value_from_json(fields.get("value")?, path)?)),
+        "ByteArray" => {
+            let hex = fields.str("hex")?;
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| fields.error("`hex` must be pairs of hex digits".to_string()))?;
+            Value::ByteArray(bytes)
+        }
+        "VectorInt" => Value::VectorInt(items_from_json(&fields, integer_from_json)?, fields.bool("fixed_length")?),
+        "VectorUInt" => Value::VectorUInt(items_from_json(&fields, integer_from_json)?, fields.bool("fixed_length")?),
+        "VectorDouble" => Value::VectorDouble(items_from_json(&fields, number_from_json)?, fields.bool("fixed_length")?),
+        "VectorObject" => Value::VectorObject(
+            values_from_json(fields.array("values")?, path)?,
+            fields.str("type_name")?.to_string(),
+            fields.bool("fixed_length")?,
+        ),
+        "Dictionary" => {
+            let pairs = fields
+                .array("entries")?
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| {
+                    let entry_path = path.index(index);
+                    let entry = Fields::new(entry, &entry_path)?;
+                    let key = value_from_json(entry.get("key")?, &entry_path.child("<key>"))?;
+                    let value = value_from_json(entry.get("value")?, &entry_path)?;
+
+                    Ok((Rc::new(key), Rc::new(value)))
+                })
+                .collect::<Result<_, JsonError>>()?;
+            Value::Dictionary(pairs, fields.bool("weak_keys")?)
+        }
+        "Custom" => Value::Custom(
+            elements_from_json(fields.array("external")?, path)?,
+            elements_from_json(fields.array("properties")?, path)?,
+            fields.class("class")?,
+        ),
+        type_name => return Err(fields.error(format!("unknown type `{}`", type_name))),
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_json_can_not_hold_are_strings() {
+        let lso = Lso::new(
+            vec![
+                Element::new("nan", Value::Number(f64::NAN)),
+                Element::new("infinity", Value::Number(f64::INFINITY)),
+                Element::new("doubles", Value::VectorDouble(vec![f64::NEG_INFINITY], false)),
+            ],
+            "numbers",
+            AMFVersion::AMF3,
+        );
+        let json = to_json(&lso);
+
+        assert_eq!(json["body"][0]["value"]["value"], "NaN");
+        assert_eq!(json["body"][1]["value"]["value"], "Infinity");
+        assert_eq!(json["body"][2]["value"]["values"][0], "-Infinity");
+
+        let read = from_json(&json).unwrap();
+        assert!(matches!(read.body[0].value(), Value::Number(number) if number.is_nan()));
+        assert_eq!(read.body[1..], lso.body[1..]);
+    }
+
+    #[test]
+    fn custom_objects_round_trip() {
+        let class = ClassDefinition {
+            name: "flex.messaging.io.ArrayCollection".to_string(),
+            attributes: Attribute::External.into(),
+            static_properties: Vec::new(),
+        };
+        let value = Value::Custom(
+            vec![Element::new("source", Value::StrictArray(vec![Rc::new(Value::Integer(1))]))],
+            vec![Element::new("extra", Value::Null)],
+            Some(class),
+        );
+        let lso = Lso::new(vec![Element::new("collection", value)], "custom", AMFVersion::AMF3);
+
+        assert_eq!(from_str(&to_string(&lso)).unwrap().body, lso.body);
+    }
+
+    #[test]
+    fn reports_where_json_is_invalid() {
+        let json = json!({
+            "name": "t",
+            "version": "AMF3",
+            "body": [{ "name": "a", "value": { "type": "Object", "class": null, "properties": [
+                { "name": "b", "value": { "type": "Integer", "value": 1.5 } }
+            ] } }]
+        });
+
+        match from_json(&json) {
+            Err(JsonError::Schema { path, .. }) => assert_eq!(path.to_string(), "a.b"),
+            result => panic!("expected a schema error, got {:?}", result.map(|lso| lso.body)),
+        }
+        assert!(matches!(from_str("{"), Err(JsonError::Syntax(_))));
+    }
+}
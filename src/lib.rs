@@ -1,21 +1,59 @@
+//! Reading, editing and writing Flash Local Shared Object (SOL) files.
+//!
+//! `SolDocument` opens, edits and saves a file, values in it are addressed by a `ValuePath`.
+//! The editor is only built with the `gui` feature, which is on by default.
+
 #![forbid(unsafe_code)]
 #![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod amf0;
+mod amf3;
+#[cfg(feature = "gui")]
 mod app;
+#[cfg(feature = "gui")]
+mod byte_array;
+mod cli;
+mod convert;
+pub mod custom;
+mod date;
+mod diff;
+pub mod document;
+pub mod history;
+mod json;
+#[cfg(feature = "gui")]
+mod merge;
+pub mod path;
+pub mod query;
+mod recover;
+#[cfg(feature = "gui")]
+mod replace;
+#[cfg(feature = "gui")]
+mod retype;
+mod script;
+#[cfg(feature = "gui")]
+mod search;
+mod text;
+pub mod value;
+#[cfg(feature = "gui")]
 pub use app::App;
+pub use cli::run_cli;
+pub use convert::ConvertError;
+pub use document::{OpenError, SolDocument};
+pub use flash_lso::types::{AMFVersion, Element, Lso, Value};
+pub use path::ValuePath;
 
 // ----------------------------------------------------------------------------
 // When compiling for web:
 
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", feature = "gui"))]
 use eframe::wasm_bindgen::{self, prelude::*};
 
 /// This is the entry-point for all the web-assembly.
 /// This is called once from the HTML.
 /// It loads the app, installs some callbacks, then returns.
 /// You can add more callbacks like this if you want to call in to your code.
-#[cfg(target_arch = "wasm32")]
+#[cfg(all(target_arch = "wasm32", feature = "gui"))]
 #[wasm_bindgen]
 pub fn start(canvas_id: &str) -> Result<(), eframe::wasm_bindgen::JsValue> {
     let app = TemplateApp::default();
@@ -5,7 +5,17 @@
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let app = eframe_template::App::default();
-    let native_options = eframe::NativeOptions::default();
-    eframe::run_native(Box::new(app), native_options);
+    // Any arguments run a command line command instead of the editor, which is only built with
+    // the `gui` feature
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() || cfg!(not(feature = "gui")) {
+        std::process::exit(eframe_template::run_cli(&args));
+    }
+
+    #[cfg(feature = "gui")]
+    {
+        let app = eframe_template::App::default();
+        let native_options = eframe::NativeOptions::default();
+        eframe::run_native(Box::new(app), native_options);
+    }
 }
@@ -0,0 +1,333 @@
+//! Three-way merge of documents that diverged from a common base.
+//!
+//! Values changed on one side only are taken from that side. Containers of the same kind changed
+//! on both sides are merged child by child, elements by name and array items by index. A value
+//! changed differently on both sides is a conflict, resolved with the side picked for its path.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Header, Lso, Value};
+
+use crate::diff;
+use crate::path::ValuePath;
+
+/// One of the versions being merged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Base,
+    Ours,
+    Theirs,
+}
+
+/// A value changed differently on both sides. `None` means the value was removed, or that it
+/// didn't exist yet in the base.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    pub path: ValuePath,
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+impl Conflict {
+    pub fn side(&self, side: Side) -> Option<&Value> {
+        match side {
+            Side::Base => self.base.as_ref(),
+            Side::Ours => self.ours.as_ref(),
+            Side::Theirs => self.theirs.as_ref(),
+        }
+    }
+}
+
+pub struct Merge {
+    pub lso: Lso,
+    /// Every conflict, including the ones resolved by a choice.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merges `ours` and `theirs`, which were both changed from `base`. Each conflict is resolved
+/// with its side in `choices`, or with ours if there is none.
+pub fn merge(base: &Lso, ours: &Lso, theirs: &Lso, choices: &HashMap<ValuePath, Side>) -> Merge {
+    let mut merger = Merger {
+        choices,
+        conflicts: Vec::new(),
+    };
+
+    let name = if ours.header.name == base.header.name {
+        &theirs.header.name
+    } else {
+        &ours.header.name
+    };
+    let body = merger.merge_elements(&base.body, &ours.body, &theirs.body, &ValuePath::default());
+
+    Merge {
+        lso: Lso {
+            header: Header {
+                length: 0,
+                name: name.clone(),
+                format_version: ours.header.format_version,
+            },
+            body,
+        },
+        conflicts: merger.conflicts,
+    }
+}
+
+struct Merger<'a> {
+    choices: &'a HashMap<ValuePath, Side>,
+    conflicts: Vec<Conflict>,
+}
+
+impl Merger<'_> {
+    /// Merges a value that may be missing on any side, returns `None` if it is removed.
+    fn merge_option(
+        &mut self,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+        path: &ValuePath,
+    ) -> Option<Value> {
+        let same = |a: Option<&Value>, b: Option<&Value>| match (a, b) {
+            (Some(a), Some(b)) => diff::same(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+
+        if same(ours, theirs) || same(base, theirs) {
+            return ours.cloned();
+        }
+        if same(base, ours) {
+            return theirs.cloned();
+        }
+
+        if let (Some(base), Some(ours), Some(theirs)) = (base, ours, theirs) {
+            if let Some(merged) = self.merge_children(base, ours, theirs, path) {
+                return Some(merged);
+            }
+        }
+
+        let conflict = Conflict {
+            path: path.clone(),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        };
+        let side = self.choices.get(path).copied().unwrap_or(Side::Ours);
+        let value = conflict.side(side).cloned();
+        self.conflicts.push(conflict);
+
+        value
+    }
+
+    fn merge_value(&mut self, base: &Value, ours: &Value, theirs: &Value, path: &ValuePath) -> Value {
+        // Every side has the value, so whichever side is picked there is one
+        self.merge_option(Some(base), Some(ours), Some(theirs), path).unwrap()
+    }
+
+    /// Merges containers of the same kind child by child, `None` for anything else.
+    fn merge_children(&mut self, base: &Value, ours: &Value, theirs: &Value, path: &ValuePath) -> Option<Value> {
+        let merged = match (base, ours, theirs) {
+            (Value::AMF3(base), Value::AMF3(ours), Value::AMF3(theirs)) => {
+                Value::AMF3(Rc::new(self.merge_value(base, ours, theirs, path)))
+            }
+            (Value::Object(base, base_class), Value::Object(ours, class), Value::Object(theirs, their_class))
+                if base_class == class && class == their_class =>
+            {
+                Value::Object(self.merge_elements(base, ours, theirs, path), class.clone())
+            }
+            (
+                Value::ECMAArray(base_dense, base, _),
+                Value::ECMAArray(ours_dense, ours, _),
+                Value::ECMAArray(theirs_dense, theirs, _),
+            ) if base_dense.len() == ours_dense.len() && ours_dense.len() == theirs_dense.len() => {
+                let dense = self.merge_items(base_dense, ours_dense, theirs_dense, path);
+                let elements = self.merge_elements(base, ours, theirs, path);
+                // The declared length counts the properties, as edits keep it
+                let length = elements.len() as u32;
+
+                Value::ECMAArray(dense, elements, length)
+            }
+            (Value::StrictArray(base), Value::StrictArray(ours), Value::StrictArray(theirs))
+                if base.len() == ours.len() && ours.len() == theirs.len() =>
+            {
+                Value::StrictArray(self.merge_items(base, ours, theirs, path))
+            }
+            (
+                Value::Custom(base_custom, base, base_class),
+                Value::Custom(ours_custom, ours, class),
+                Value::Custom(theirs_custom, theirs, their_class),
+            ) if base_class == class && class == their_class => Value::Custom(
+                self.merge_elements(base_custom, ours_custom, theirs_custom, path),
+                self.merge_elements(base, ours, theirs, path),
+                class.clone(),
+            ),
+            _ => return None,
+        };
+
+        Some(merged)
+    }
+
+    fn merge_items(
+        &mut self,
+        base: &[Rc<Value>],
+        ours: &[Rc<Value>],
+        theirs: &[Rc<Value>],
+        path: &ValuePath,
+    ) -> Vec<Rc<Value>> {
+        base.iter()
+            .zip(ours)
+            .zip(theirs)
+            .enumerate()
+            .map(|(index, ((base, ours), theirs))| Rc::new(self.merge_value(base, ours, theirs, &path.index(index))))
+            .collect()
+    }
+
+    /// Merges elements matched by name, keeping the order of ours with the elements only
+    /// added by theirs at the end.
+    fn merge_elements(
+        &mut self,
+        base: &[Element],
+        ours: &[Element],
+        theirs: &[Element],
+        path: &ValuePath,
+    ) -> Vec<Element> {
+        let base = keyed(base);
+        let ours = keyed(ours);
+        let theirs = keyed(theirs);
+
+        let mut keys: Vec<&Key> = ours.iter().map(|(key, _)| key).collect();
+        keys.extend(theirs.iter().map(|(key, _)| key).filter(|key| !ours.iter().any(|(other, _)| other == *key)));
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let value = self.merge_option(find(&base, key), find(&ours, key), find(&theirs, key), &path.child(&key.0))?;
+                Some(Element::new(key.0.clone(), value))
+            })
+            .collect()
+    }
+}
+
+/// An element name and how many elements before it have the same name, so repeated names are
+/// matched in the order they appear.
+type Key = (String, usize);
+
+fn keyed(elements: &[Element]) -> Vec<(Key, &Value)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+
+    elements
+        .iter()
+        .map(|element| {
+            let count = counts.entry(&element.name).or_default();
+            let key = (element.name.clone(), *count);
+            *count += 1;
+            (key, element.value())
+        })
+        .collect()
+}
+
+fn find<'a>(elements: &[(Key, &'a Value)], key: &Key) -> Option<&'a Value> {
+    elements
+        .iter()
+        .find(|(other, _)| other == key)
+        .map(|(_, value)| *value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flash_lso::types::AMFVersion;
+
+    fn lso(name: &str, body: Vec<Element>) -> Lso {
+        Lso::new(body, name, AMFVersion::AMF3)
+    }
+
+    fn player(name: &str, level: f64) -> Element {
+        Element::new(
+            "player",
+            Value::Object(
+                vec![
+                    Element::new("name", Value::String(name.to_string())),
+                    Element::new("level", Value::Number(level)),
+                ],
+                None,
+            ),
+        )
+    }
+
+    fn path(path: &str) -> ValuePath {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn takes_changes_from_both_sides() {
+        let base = lso("save", vec![player("Alice", 1.0), Element::new("gold", Value::Integer(10))]);
+        let ours = lso("save", vec![player("Bob", 1.0), Element::new("gold", Value::Integer(10))]);
+        let theirs = lso("renamed", vec![player("Alice", 2.0), Element::new("new", Value::Null)]);
+
+        let merge = merge(&base, &ours, &theirs, &HashMap::new());
+
+        assert!(merge.conflicts.is_empty());
+        assert_eq!(merge.lso.header.name, "renamed");
+        assert_eq!(merge.lso.body, vec![player("Bob", 2.0), Element::new("new", Value::Null)]);
+    }
+
+    #[test]
+    fn conflicts_are_resolved_by_choice() {
+        let base = lso("save", vec![player("Alice", 1.0)]);
+        let ours = lso("save", vec![player("Alice", 2.0)]);
+        let theirs = lso("save", vec![player("Alice", 3.0)]);
+
+        let merged = merge(&base, &ours, &theirs, &HashMap::new());
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].path, path("player.level"));
+        assert_eq!(merged.conflicts[0].side(Side::Base), Some(&Value::Number(1.0)));
+        assert_eq!(merged.lso.body, vec![player("Alice", 2.0)]);
+
+        let choices = vec![(path("player.level"), Side::Theirs)].into_iter().collect();
+        let merged = merge(&base, &ours, &theirs, &choices);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.lso.body, vec![player("Alice", 3.0)]);
+    }
+
+    #[test]
+    fn removal_against_a_change_conflicts() {
+        let base = lso("save", vec![Element::new("a", Value::Number(1.0)), Element::new("b", Value::Null)]);
+        let ours = lso("save", vec![Element::new("b", Value::Null)]);
+        let theirs = lso("save", vec![Element::new("a", Value::Number(2.0)), Element::new("b", Value::Null)]);
+
+        let merged = merge(&base, &ours, &theirs, &HashMap::new());
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].ours, None);
+        assert_eq!(merged.lso.body, vec![Element::new("b", Value::Null)]);
+
+        let choices = vec![(path("a"), Side::Theirs)].into_iter().collect();
+        let merged = merge(&base, &ours, &theirs, &choices);
+        assert_eq!(merged.lso.body, vec![Element::new("b", Value::Null), Element::new("a", Value::Number(2.0))]);
+    }
+
+    #[test]
+    fn unchanged_nan_is_not_a_change() {
+        let base = lso("save", vec![Element::new("a", Value::Number(f64::NAN)), Element::new("b", Value::Bool(false))]);
+        let theirs = lso("save", vec![Element::new("a", Value::Number(f64::NAN)), Element::new("b", Value::Bool(true))]);
+
+        let merged = merge(&base, &base, &theirs, &HashMap::new());
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.lso.body[1], Element::new("b", Value::Bool(true)));
+    }
+
+    #[test]
+    fn ecma_array_length_counts_the_merged_properties() {
+        let array = |names: &[&str]| {
+            let elements = names.iter().map(|name| Element::new(*name, Value::Null)).collect();
+            lso("save", vec![Element::new("map", Value::ECMAArray(Vec::new(), elements, names.len() as u32))])
+        };
+        let base = array(&["a", "b"]);
+        let ours = array(&["a", "b", "c"]);
+        let theirs = array(&["b"]);
+
+        let merged = merge(&base, &ours, &theirs, &HashMap::new());
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.lso.body, array(&["b", "c"]).body);
+    }
+}
@@ -0,0 +1,397 @@
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use flash_lso::types::{Element, Value};
+
+/// One step from a value to one of its children.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    /// A named child, such as an object property.
+    Name(String),
+    /// A positional child, such as an array entry.
+    Index(usize),
+}
+
+/// The location of a value inside `Lso.body`, e.g. `player.inventory[3].count`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValuePath(Vec<Segment>);
+
+impl ValuePath {
+    /// The path of the child called `name` below this one.
+    pub fn child(&self, name: impl Into<String>) -> Self {
+        self.join(Segment::Name(name.into()))
+    }
+
+    /// The path of the child at `index` below this one.
+    pub fn index(&self, index: usize) -> Self {
+        self.join(Segment::Index(index))
+    }
+
+    pub fn join(&self, segment: Segment) -> Self {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        Self(segments)
+    }
+
+    /// Whether this path is `ancestor` or below it.
+    pub fn starts_with(&self, ancestor: &ValuePath) -> bool {
+        self.0.starts_with(&ancestor.0)
+    }
+
+    /// The value at this path in `body`.
+    pub fn get<'a>(&self, body: &'a [Element]) -> Option<&'a Value> {
+        let (first, rest) = self.0.split_first()?;
+        let element = find_element(body, first)?;
+
+        rest.iter().try_fold(element.value(), |value, segment| get_in(value, segment))
+    }
+
+    /// The value at this path in `body`, ready to be changed.
+    pub fn get_mut<'a>(&self, body: &'a mut [Element]) -> Option<&'a mut Value> {
+        let (first, rest) = self.0.split_first()?;
+        let mut value = &mut find_element_mut(body, first)?.value;
+
+        for segment in rest {
+            value = child_mut(value, segment)?;
+        }
+
+        Some(Rc::make_mut(value))
+    }
+
+    /// The paths of the values from the top level element down to this one.
+    pub fn prefixes(&self) -> impl Iterator<Item = ValuePath> + '_ {
+        (1..=self.0.len()).map(move |length| ValuePath(self.0[..length].to_vec()))
+    }
+
+    /// The path of the value this one is in, the root path for top level elements.
+    pub fn parent(&self) -> Option<ValuePath> {
+        let (_, parent) = self.0.split_last()?;
+        Some(Self(parent.to_vec()))
+    }
+
+    /// The step from the parent of the value at this path to it.
+    pub fn last(&self) -> Option<&Segment> {
+        self.0.last()
+    }
+
+    /// Whether this is the path of the body itself rather than of a value in it.
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replaces the value at this path in `body`, returns whether the path was found.
+    pub fn set(&self, body: &mut [Element], value: Value) -> bool {
+        let (first, rest) = match self.0.split_first() {
+            Some(split) => split,
+            None => return false,
+        };
+
+        match find_element_mut(body, first) {
+            Some(element) => set_in(&mut element.value, rest, value),
+            None => false,
+        }
+    }
+}
+
+fn find_element<'a>(elements: &'a [Element], segment: &Segment) -> Option<&'a Element> {
+    match segment {
+        Segment::Name(name) => elements.iter().find(|element| &element.name == name),
+        Segment::Index(_) => None,
+    }
+}
+
+fn find_element_mut<'a>(elements: &'a mut [Element], segment: &Segment) -> Option<&'a mut Element> {
+    match segment {
+        Segment::Name(name) => elements.iter_mut().find(|element| &element.name == name),
+        Segment::Index(_) => None,
+    }
+}
+
+fn get_in<'a>(value: &'a Value, segment: &Segment) -> Option<&'a Value> {
+    let child = match (value, segment) {
+        (Value::AMF3(inner), _) => return get_in(inner, segment),
+        (Value::Object(elements, _), _) | (Value::ECMAArray(_, elements, _), Segment::Name(_)) => {
+            find_element(elements, segment)?.value()
+        }
+        (Value::Custom(custom_elements, elements, _), _) => find_element(elements, segment)
+            .or_else(|| find_element(custom_elements, segment))?
+            .value(),
+        (Value::ECMAArray(values, _, _), Segment::Index(index))
+        | (Value::StrictArray(values), Segment::Index(index))
+        | (Value::VectorObject(values, _, _), Segment::Index(index)) => values.get(*index)?,
+        (Value::Dictionary(pairs, _), Segment::Index(index)) => &pairs.get(*index)?.1,
+        _ => return None,
+    };
+
+    Some(child)
+}
+
+fn set_in(value: &mut Rc<Value>, segments: &[Segment], new_value: Value) -> bool {
+    match segments.split_first() {
+        Some((segment, rest)) => match child_mut(value, segment) {
+            Some(child) => set_in(child, rest, new_value),
+            None => false,
+        },
+        None => {
+            *value = Rc::new(new_value);
+            true
+        }
+    }
+}
+
+/// The child of `value` at `segment`, ready to be changed.
+fn child_mut<'a>(value: &'a mut Rc<Value>, segment: &Segment) -> Option<&'a mut Rc<Value>> {
+    // Only clone the values along the path if they are shared
+    match (Rc::make_mut(value), segment) {
+        (Value::AMF3(inner), _) => child_mut(inner, segment),
+        (Value::Object(elements, _), _) | (Value::ECMAArray(_, elements, _), Segment::Name(_)) => {
+            find_element_mut(elements, segment).map(|element| &mut element.value)
+        }
+        (Value::Custom(custom_elements, elements, _), Segment::Name(name)) => elements
+            .iter_mut()
+            .chain(custom_elements.iter_mut())
+            .find(|element| &element.name == name)
+            .map(|element| &mut element.value),
+        (Value::ECMAArray(values, _, _), Segment::Index(index))
+        | (Value::StrictArray(values), Segment::Index(index))
+        | (Value::VectorObject(values, _, _), Segment::Index(index)) => values.get_mut(*index),
+        (Value::Dictionary(pairs, _), Segment::Index(index)) => pairs.get_mut(*index).map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+/// Whether `name` can be written as a bare word in a dotted path.
+fn is_plain_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_alphabetic() || first == '_' || first == '$' => {
+            chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        }
+        _ => false,
+    }
+}
+
+impl fmt::Display for ValuePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Name(name) if is_plain_name(name) => {
+                    if i != 0 {
+                        f.write_str(".")?;
+                    }
+                    f.write_str(name)?;
+                }
+                Segment::Name(name) => write!(f, "[{:?}]", name)?,
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The error returned when a string isn't a valid `ValuePath`.
+#[derive(Debug, PartialEq)]
+pub struct ParsePathError {
+    /// Byte offset in the string where the path became invalid.
+    pub position: usize,
+}
+
+impl fmt::Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid path at character {}", self.position + 1)
+    }
+}
+
+impl std::error::Error for ParsePathError {}
+
+impl FromStr for ValuePath {
+    type Err = ParsePathError;
+
+    /// Parses the format written by `Display`, e.g. `a.b[3]["weird name"]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut chars = s.char_indices().peekable();
+
+        while let Some(&(position, c)) = chars.peek() {
+            let error = ParsePathError { position };
+
+            match c {
+                '[' => {
+                    chars.next();
+
+                    if chars.peek().map(|&(_, c)| c) == Some('"') {
+                        chars.next();
+                        segments.push(Segment::Name(parse_quoted(&mut chars).ok_or(error)?));
+                    } else {
+                        let mut digits = String::new();
+                        while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit()) {
+                            digits.push(c);
+                            chars.next();
+                        }
+                        segments.push(Segment::Index(digits.parse().map_err(|_| error)?));
+                    }
+
+                    match chars.next() {
+                        Some((_, ']')) => {}
+                        Some((position, _)) => return Err(ParsePathError { position }),
+                        None => return Err(ParsePathError { position: s.len() }),
+                    }
+                }
+                '.' if !segments.is_empty() => {
+                    chars.next();
+
+                    let start = chars.peek().map_or(s.len(), |&(position, _)| position);
+                    let name = take_name(s, &mut chars);
+                    if name.is_empty() {
+                        return Err(ParsePathError { position: start });
+                    }
+                    segments.push(Segment::Name(name));
+                }
+                _ if segments.is_empty() => {
+                    let name = take_name(s, &mut chars);
+                    if name.is_empty() {
+                        return Err(error);
+                    }
+                    segments.push(Segment::Name(name));
+                }
+                _ => return Err(error),
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(ParsePathError { position: 0 });
+        }
+
+        Ok(Self(segments))
+    }
+}
+
+type CharIndices<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+/// Takes the longest plain name at the start of `chars`.
+fn take_name(s: &str, chars: &mut CharIndices<'_>) -> String {
+    let start = match chars.peek() {
+        Some(&(position, _)) => position,
+        None => return String::new(),
+    };
+
+    let mut end = start;
+    while let Some(&(position, c)) = chars.peek() {
+        if !is_plain_name(&s[start..position + c.len_utf8()]) {
+            break;
+        }
+        end = position + c.len_utf8();
+        chars.next();
+    }
+
+    s[start..end].to_string()
+}
+
+/// Reads the rest of a quoted name after its opening quote, undoing the escapes of `{:?}`.
+fn parse_quoted(chars: &mut CharIndices<'_>) -> Option<String> {
+    let mut name = String::new();
+
+    loop {
+        match chars.next()?.1 {
+            '"' => return Some(name),
+            '\\' => match chars.next()?.1 {
+                'n' => name.push('\n'),
+                'r' => name.push('\r'),
+                't' => name.push('\t'),
+                '0' => name.push('\0'),
+                'u' => {
+                    if chars.next()?.1 != '{' {
+                        return None;
+                    }
+
+                    let mut hex = String::new();
+                    loop {
+                        match chars.next()?.1 {
+                            '}' => break,
+                            c => hex.push(c),
+                        }
+                    }
+                    name.push(std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                c => name.push(c),
+            },
+            c => name.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &str) -> ValuePath {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_what_display_writes() {
+        let weird = ValuePath::default()
+            .child("player")
+            .child("with space")
+            .index(3)
+            .child("quote\"\n\u{1}")
+            .child("_x$1");
+
+        assert_eq!(weird.to_string(), r#"player["with space"][3]["quote\"\n\u{1}"]._x$1"#);
+        assert_eq!(path(&weird.to_string()), weird);
+        assert_eq!(path("[0]"), ValuePath::default().index(0));
+        assert_eq!(path(r#"["1st"].b"#), ValuePath::default().child("1st").child("b"));
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        let error = |path: &str| path.parse::<ValuePath>().unwrap_err().position;
+
+        assert_eq!(error(""), 0);
+        assert_eq!(error(".a"), 0);
+        assert_eq!(error("a."), 2);
+        assert_eq!(error("a[x]"), 1);
+        assert_eq!(error("a[1"), 3);
+        assert_eq!(error(r#"a["b"#), 1);
+        assert_eq!(error("a b"), 1);
+    }
+
+    #[test]
+    fn gets_and_sets_through_amf3_values() {
+        let object = Value::Object(vec![Element::new("count", Value::Integer(5))], None);
+        let mut body = vec![
+            Element::new("w", Value::AMF3(Rc::new(object))),
+            Element::new("list", Value::StrictArray(vec![Rc::new(Value::Null), Rc::new(Value::Bool(true))])),
+        ];
+
+        assert_eq!(path("w.count").get(&body), Some(&Value::Integer(5)));
+        assert_eq!(path("list[1]").get(&body), Some(&Value::Bool(true)));
+        assert_eq!(path("list[2]").get(&body), None);
+        assert_eq!(path("missing").get(&body), None);
+
+        assert!(path("w.count").set(&mut body, Value::Integer(6)));
+        assert_eq!(path("w.count").get(&body), Some(&Value::Integer(6)));
+        assert!(!path("w.other").set(&mut body, Value::Null));
+        assert!(!ValuePath::default().set(&mut body, Value::Null));
+    }
+
+    #[test]
+    fn prefixes_from_the_top() {
+        let prefixes: Vec<String> = path("a.b[1]").prefixes().map(|prefix| prefix.to_string()).collect();
+
+        assert_eq!(prefixes, ["a", "a.b", "a.b[1]"]);
+        assert!(path("a.b[1]").starts_with(&path("a.b")));
+        assert!(!path("a.bc").starts_with(&path("a.b")));
+    }
+
+    #[test]
+    fn parents() {
+        assert_eq!(path("a.b").parent(), Some(path("a")));
+        assert_eq!(path("a").parent(), Some(ValuePath::default()));
+        assert_eq!(ValuePath::default().parent(), None);
+        assert_eq!(path("a[2]").last(), Some(&Segment::Index(2)));
+    }
+}
@@ -0,0 +1,713 @@
+//! Queries selecting values in a document, e.g. `player.inventory[*].count` or
+//! `..items[?(@.level > 10)]`.
+//!
+//! A query is a path whose steps can also be wildcards (`*`, `[*]`), recursive descents (`..name`,
+//! `..*`) matching at any depth, and filters (`[?(...)]`) keeping the children for which a
+//! predicate about `@`, the child, holds. Predicates compare a value below `@` with a literal
+//! using `==`, `!=`, `<`, `<=`, `>` or `>=`, check that it exists, and combine with `&&`, `||`,
+//! `!` and parentheses.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use flash_lso::types::{Element, Value};
+
+use crate::path::{Segment, ValuePath};
+
+/// A value selected by a query.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub path: ValuePath,
+    pub value: Value,
+}
+
+#[derive(Clone, Debug)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+impl Query {
+    /// The values in `body` the query selects, each once. Values matched by a recursive descent
+    /// are listed with their siblings, parents before children.
+    pub fn run(&self, body: &[Element]) -> Vec<Match> {
+        let mut nodes = vec![(ValuePath::default(), Node::Body(body))];
+
+        for step in &self.steps {
+            nodes = step.select(&nodes);
+        }
+
+        // Recursive descents can reach a value from several of the nodes before them
+        let mut seen = HashSet::new();
+        nodes
+            .into_iter()
+            .filter_map(|(path, node)| match node {
+                Node::Value(value) if seen.insert(path.clone()) => Some(Match {
+                    path,
+                    value: value.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            position: 0,
+        };
+
+        parser.query()
+    }
+}
+
+/// The error returned when a string isn't a valid query.
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    /// Character offset in the query where it became invalid.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid query at character {}: {}", self.position + 1, self.message)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Every value in `body` with its path, parents before their children.
+pub fn values(body: &[Element]) -> Vec<(ValuePath, &Value)> {
+    Node::Body(body)
+        .descendants(&ValuePath::default())
+        .into_iter()
+        .filter_map(|(path, node)| match node {
+            Node::Value(value) => Some((path, value)),
+            Node::Body(_) => None,
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    /// Select among every descendant rather than only the children.
+    recursive: bool,
+    selector: Selector,
+}
+
+#[derive(Clone, Debug)]
+enum Selector {
+    Segment(Segment),
+    Wildcard,
+    Filter(Expression),
+}
+
+#[derive(Clone, Debug)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// The value at the path below `@` exists.
+    Exists(Vec<Segment>),
+    Compare(Vec<Segment>, Operator, Literal),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+/// A place in the document: the body, or a value in it.
+#[derive(Clone)]
+enum Node<'a> {
+    Body(&'a [Element]),
+    Value(&'a Value),
+}
+
+impl<'a> Node<'a> {
+    fn children(&self) -> Vec<(Segment, &'a Value)> {
+        match self {
+            Node::Body(elements) => element_children(elements),
+            Node::Value(value) => value_children(value),
+        }
+    }
+
+    /// This node and everything below it.
+    fn descendants(&self, path: &ValuePath) -> Vec<(ValuePath, Node<'a>)> {
+        let mut nodes = vec![(path.clone(), self.clone())];
+
+        for (segment, child) in self.children() {
+            nodes.extend(Node::Value(child).descendants(&path.join(segment)));
+        }
+
+        nodes
+    }
+}
+
+/// The values of `elements`, by name.
+pub fn element_children(elements: &[Element]) -> Vec<(Segment, &Value)> {
+    elements
+        .iter()
+        .map(|element| (Segment::Name(element.name.clone()), element.value()))
+        .collect()
+}
+
+/// The values directly below `value`, with the step to each. Items of numeric vectors aren't
+/// stored as values, so paths can't address them and they aren't listed.
+pub fn value_children(value: &Value) -> Vec<(Segment, &Value)> {
+    match value {
+        Value::AMF3(inner) => value_children(inner),
+        Value::Object(elements, _) => element_children(elements),
+        Value::ECMAArray(dense, elements, _) => {
+            let mut children = indexed(borrowed(dense));
+            children.extend(element_children(elements));
+            children
+        }
+        Value::StrictArray(values) | Value::VectorObject(values, _, _) => indexed(borrowed(values)),
+        Value::Dictionary(pairs, _) => indexed(pairs.iter().map(|(_, value)| value.as_ref())),
+        Value::Custom(custom_elements, elements, _) => {
+            let mut children = element_children(custom_elements);
+            children.extend(element_children(elements));
+            children
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn borrowed(values: &[Rc<Value>]) -> impl Iterator<Item = &Value> {
+    values.iter().map(|value| value.as_ref())
+}
+
+fn indexed<'a>(values: impl Iterator<Item = &'a Value>) -> Vec<(Segment, &'a Value)> {
+    values.enumerate().map(|(index, value)| (Segment::Index(index), value)).collect()
+}
+
+impl Step {
+    fn select<'a>(&self, nodes: &[(ValuePath, Node<'a>)]) -> Vec<(ValuePath, Node<'a>)> {
+        let mut selected = Vec::new();
+
+        for (path, node) in nodes {
+            let candidates = if self.recursive {
+                node.descendants(path)
+            } else {
+                vec![(path.clone(), node.clone())]
+            };
+
+            for (path, node) in candidates {
+                for (segment, child) in node.children() {
+                    if self.selector.matches(&segment, child) {
+                        selected.push((path.join(segment), Node::Value(child)));
+                    }
+                }
+            }
+        }
+
+        selected
+    }
+}
+
+impl Selector {
+    fn matches(&self, segment: &Segment, value: &Value) -> bool {
+        match self {
+            Selector::Segment(expected) => expected == segment,
+            Selector::Wildcard => true,
+            Selector::Filter(expression) => expression.holds(value),
+        }
+    }
+}
+
+impl Expression {
+    fn holds(&self, value: &Value) -> bool {
+        match self {
+            Expression::Or(left, right) => left.holds(value) || right.holds(value),
+            Expression::And(left, right) => left.holds(value) && right.holds(value),
+            Expression::Not(expression) => !expression.holds(value),
+            Expression::Exists(path) => lookup(value, path).is_some(),
+            Expression::Compare(path, operator, literal) => match lookup(value, path) {
+                Some(value) => operator.compare(value, literal),
+                None => false,
+            },
+        }
+    }
+}
+
+/// The value at `path` below `value`.
+fn lookup<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    let (segment, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Some(value),
+    };
+
+    let child = value_children(value)
+        .into_iter()
+        .find(|(other, _)| other == segment)
+        .map(|(_, child)| child)?;
+
+    lookup(child, rest)
+}
+
+impl Operator {
+    fn compare(self, value: &Value, literal: &Literal) -> bool {
+        let value = match value {
+            Value::AMF3(inner) => inner,
+            value => value,
+        };
+
+        let ordering = match (value, literal) {
+            (Value::Number(number), Literal::Number(other)) => number.partial_cmp(other),
+            (Value::Integer(integer), Literal::Number(other)) => f64::from(*integer).partial_cmp(other),
+            (Value::Date(millis, _), Literal::Number(other)) => millis.partial_cmp(other),
+            (Value::String(string), Literal::String(other)) | (Value::XML(string, _), Literal::String(other)) => {
+                Some(string.as_str().cmp(other))
+            }
+            (Value::Bool(bool), Literal::Bool(other)) => Some(bool.cmp(other)),
+            (Value::Null, Literal::Null) | (Value::Undefined, Literal::Null) => Some(Ordering::Equal),
+            _ => None,
+        };
+
+        match (self, ordering) {
+            // Values of different types are never equal
+            (Operator::NotEqual, None) => true,
+            (_, None) => false,
+            (Operator::Equal, Some(ordering)) => ordering == Ordering::Equal,
+            (Operator::NotEqual, Some(ordering)) => ordering != Ordering::Equal,
+            (Operator::Less, Some(ordering)) => ordering == Ordering::Less,
+            (Operator::LessOrEqual, Some(ordering)) => ordering != Ordering::Greater,
+            (Operator::Greater, Some(ordering)) => ordering == Ordering::Greater,
+            (Operator::GreaterOrEqual, Some(ordering)) => ordering != Ordering::Less,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn query(&mut self) -> Result<Query, QueryError> {
+        // `$` only names the body when it isn't the start of a name
+        if self.peek() == Some('$') && matches!(self.peek_at(1), None | Some('.') | Some('[')) {
+            self.position += 1;
+        }
+
+        let mut steps = Vec::new();
+
+        while let Some(c) = self.peek() {
+            let step = match c {
+                '.' if self.peek_at(1) == Some('.') => {
+                    self.position += 2;
+                    let selector = match self.peek() {
+                        Some('[') => self.bracket()?,
+                        _ => self.name_or_wildcard()?,
+                    };
+
+                    Step {
+                        recursive: true,
+                        selector,
+                    }
+                }
+                '.' => {
+                    self.position += 1;
+                    Step {
+                        recursive: false,
+                        selector: self.name_or_wildcard()?,
+                    }
+                }
+                '[' => Step {
+                    recursive: false,
+                    selector: self.bracket()?,
+                },
+                _ if steps.is_empty() => Step {
+                    recursive: false,
+                    selector: self.name_or_wildcard()?,
+                },
+                _ => return Err(self.error("expected `.`, `..` or `[`")),
+            };
+
+            steps.push(step);
+        }
+
+        Ok(Query { steps })
+    }
+
+    fn name_or_wildcard(&mut self) -> Result<Selector, QueryError> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+
+        Ok(Selector::Segment(Segment::Name(self.name()?)))
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_' || c == '$') {
+            self.position += 1;
+        }
+
+        if self.position == start {
+            return Err(self.error("expected a name"));
+        }
+
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    /// A step in brackets: an index, a quoted name, a wildcard or a filter.
+    fn bracket(&mut self) -> Result<Selector, QueryError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        let selector = match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                Selector::Wildcard
+            }
+            Some('?') => {
+                self.position += 1;
+                self.expect('(')?;
+                let expression = self.expression()?;
+                self.expect(')')?;
+                Selector::Filter(expression)
+            }
+            _ => Selector::Segment(self.segment_in_brackets()?),
+        };
+
+        self.skip_whitespace();
+        self.expect(']')?;
+        Ok(selector)
+    }
+
+    fn segment_in_brackets(&mut self) -> Result<Segment, QueryError> {
+        match self.peek() {
+            Some('"') | Some('\'') => Ok(Segment::Name(self.quoted()?)),
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.position;
+                while matches!(self.peek(), Some(c) if c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+
+                let digits: String = self.chars[start..self.position].iter().collect();
+                digits
+                    .parse()
+                    .map(Segment::Index)
+                    .map_err(|_| QueryError {
+                        position: start,
+                        message: "index too large".to_string(),
+                    })
+            }
+            _ => Err(self.error("expected an index, a quoted name, `*` or `?(`")),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, QueryError> {
+        let mut expression = self.conjunction()?;
+
+        while self.eat_operator("||") {
+            expression = Expression::Or(Box::new(expression), Box::new(self.conjunction()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn conjunction(&mut self) -> Result<Expression, QueryError> {
+        let mut expression = self.unary()?;
+
+        while self.eat_operator("&&") {
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, QueryError> {
+        self.skip_whitespace();
+
+        if self.peek() == Some('!') && self.peek_at(1) != Some('=') {
+            self.position += 1;
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expression = self.expression()?;
+            self.skip_whitespace();
+            self.expect(')')?;
+            return Ok(expression);
+        }
+
+        let path = self.relative_path()?;
+        self.skip_whitespace();
+
+        let operator = [
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ]
+        .iter()
+        .find(|(text, _)| self.eat_operator(text))
+        .map(|(_, operator)| *operator);
+
+        match operator {
+            Some(operator) => Ok(Expression::Compare(path, operator, self.literal()?)),
+            None => Ok(Expression::Exists(path)),
+        }
+    }
+
+    /// `@` followed by names and indices.
+    fn relative_path(&mut self) -> Result<Vec<Segment>, QueryError> {
+        if !self.eat('@') {
+            return Err(self.error("expected `@`"));
+        }
+
+        let mut path = Vec::new();
+        loop {
+            match self.peek() {
+                Some('.') => {
+                    self.position += 1;
+                    path.push(Segment::Name(self.name()?));
+                }
+                Some('[') => {
+                    self.position += 1;
+                    path.push(self.segment_in_brackets()?);
+                    self.expect(']')?;
+                }
+                _ => return Ok(path),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal, QueryError> {
+        self.skip_whitespace();
+
+        if matches!(self.peek(), Some('"') | Some('\'')) {
+            return Ok(Literal::String(self.quoted()?));
+        }
+
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_alphanumeric() || "+-._".contains(c)) {
+            self.position += 1;
+        }
+        let word: String = self.chars[start..self.position].iter().collect();
+
+        match word.as_str() {
+            "true" => Ok(Literal::Bool(true)),
+            "false" => Ok(Literal::Bool(false)),
+            "null" => Ok(Literal::Null),
+            _ => word.parse().map(Literal::Number).map_err(|_| QueryError {
+                position: start,
+                message: "expected a number, a quoted string, true, false or null".to_string(),
+            }),
+        }
+    }
+
+    /// A string in single or double quotes, with `\` escaping the next character.
+    fn quoted(&mut self) -> Result<String, QueryError> {
+        let quote = self.peek().unwrap_or('"');
+        self.position += 1;
+
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.position += 1;
+                    string.extend(self.peek());
+                }
+                Some(c) if c == quote => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let eaten = self.peek() == Some(expected);
+        if eaten {
+            self.position += 1;
+        }
+        eaten
+    }
+
+    fn eat_operator(&mut self, operator: &str) -> bool {
+        self.skip_whitespace();
+
+        let eaten = operator.chars().enumerate().all(|(offset, c)| self.peek_at(offset) == Some(c));
+        if eaten {
+            self.position += operator.chars().count();
+        }
+        eaten
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), QueryError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> QueryError {
+        QueryError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, level: f64) -> Rc<Value> {
+        Rc::new(Value::Object(
+            vec![
+                Element::new("name", Value::String(name.to_string())),
+                Element::new("level", Value::Number(level)),
+            ],
+            None,
+        ))
+    }
+
+    fn body() -> Vec<Element> {
+        vec![
+            Element::new(
+                "player",
+                Value::Object(
+                    vec![
+                        Element::new("name", Value::String("Alice".to_string())),
+                        Element::new("items", Value::StrictArray(vec![item("sword", 12.0), item("shield", 3.0)])),
+                    ],
+                    None,
+                ),
+            ),
+            Element::new("stash", Value::AMF3(Rc::new(Value::VectorInt(vec![7, 8], false)))),
+            Element::new("name", Value::Null),
+        ]
+    }
+
+    fn run(query: &str) -> Vec<String> {
+        let query: Query = query.parse().unwrap();
+        query.run(&body()).into_iter().map(|found| found.path.to_string()).collect()
+    }
+
+    #[test]
+    fn paths_and_wildcards() {
+        assert_eq!(run("player.name"), ["player.name"]);
+        assert_eq!(run("$.player['name']"), ["player.name"]);
+        assert_eq!(run("player.items[1].level"), ["player.items[1].level"]);
+        assert_eq!(run("player.items[*].name"), ["player.items[0].name", "player.items[1].name"]);
+        assert_eq!(run("*"), ["player", "stash", "name"]);
+        // Items of numeric vectors can't be addressed
+        assert!(run("stash[1]").is_empty());
+        assert!(run("player.missing").is_empty());
+    }
+
+    #[test]
+    fn recursive_descent() {
+        assert_eq!(run("..name"), ["name", "player.name", "player.items[0].name", "player.items[1].name"]);
+        assert_eq!(run("player..level"), ["player.items[0].level", "player.items[1].level"]);
+        assert_eq!(run("..*").len(), 11);
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(run("..items[?(@.level > 10)]"), ["player.items[0]"]);
+        assert_eq!(run("..items[?(@.level >= 3 && @.name != 'sword')]"), ["player.items[1]"]);
+        assert_eq!(run("..items[?(@.name == \"shield\" || @.level < 0)].name"), ["player.items[1].name"]);
+        assert_eq!(run("..items[?(!(@.level <= 3))]"), ["player.items[0]"]);
+        assert_eq!(run("$[?(@.items)]"), ["player"]);
+        assert_eq!(run("$[?(@ == null)]"), ["name"]);
+        // Values of another type are never equal, so they are always different
+        assert_eq!(run("player[?(@ != 1)]"), ["player.name", "player.items"]);
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        let error = |query: &str| query.parse::<Query>().unwrap_err().position;
+
+        assert_eq!(error("a b"), 1);
+        assert_eq!(error("a."), 2);
+        assert_eq!(error("a[x]"), 2);
+        assert_eq!(error("a[?(@.b > )]"), 10);
+        assert_eq!(error("a[?(b)]"), 4);
+        assert_eq!(error("a['b"), 4);
+        assert_eq!(error("a[?(@.b"), 7);
+    }
+
+    #[test]
+    fn values_lists_parents_before_children() {
+        let paths: Vec<String> = values(&body()).into_iter().map(|(path, _)| path.to_string()).collect();
+
+        assert_eq!(
+            paths,
+            [
+                "player",
+                "player.name",
+                "player.items",
+                "player.items[0]",
+                "player.items[0].name",
+                "player.items[0].level",
+                "player.items[1]",
+                "player.items[1].name",
+                "player.items[1].level",
+                "stash",
+                "name",
+            ]
+        );
+    }
+
+    #[test]
+    fn values_can_be_addressed() {
+        let mut body = body();
+        body.push(Element::new(
+            "map",
+            Value::ECMAArray(vec![Rc::new(Value::Bool(true))], vec![Element::new("x", Value::Number(1.0))], 1),
+        ));
+        body.push(Element::new(
+            "dictionary",
+            Value::Dictionary(vec![(Rc::new(Value::String("key".to_string())), Rc::new(Value::Integer(1)))], false),
+        ));
+
+        for (path, value) in values(&body) {
+            assert_eq!(path.get(&body), Some(value), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Recovery of the readable part of corrupted SOL files.
+//!
+//! The header is read first and then the body one top-level element at a time, stopping at the
+//! first element that can't be read. Everything read before it is kept.
+
+use std::convert::TryInto;
+use std::ops::Range;
+
+use flash_lso::amf3::read::AMF3Decoder;
+use flash_lso::types::{AMFVersion, Element, Header, Lso};
+
+use crate::amf0::{self, take};
+use crate::custom::CustomClasses;
+
+const HEADER_VERSION: [u8; 2] = [0x00, 0xbf];
+const HEADER_SIGNATURE: [u8; 10] = [0x54, 0x43, 0x53, 0x4f, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00];
+const HEADER_PADDING: [u8; 3] = [0x00, 0x00, 0x00];
+
+const FORMAT_VERSION_AMF0: u8 = 0x0;
+const FORMAT_VERSION_AMF3: u8 = 0x3;
+
+const PADDING: u8 = 0x00;
+
+/// The elements read from a corrupted file.
+pub struct Recovered {
+    pub lso: Lso,
+    /// The bytes that were dropped, from the start of the first unreadable element to the end
+    /// of the file. Empty if the whole body was read.
+    pub unread: Range<usize>,
+}
+
+/// Reads as many top-level elements of `data` as possible, returns `None` if even the header
+/// can't be read.
+pub fn recover(data: &[u8], classes: &CustomClasses) -> Option<Recovered> {
+    let mut i = data;
+    let header = read_header(&mut i)?;
+
+    let mut body = Vec::new();
+    let mut decoder = classes.decoder();
+
+    while !i.is_empty() {
+        let mut rest = i;
+        let element = match header.format_version {
+            AMFVersion::AMF0 => amf0::read_element(&mut rest, classes),
+            AMFVersion::AMF3 => read_amf3_element(&mut rest, &mut decoder),
+        };
+
+        match element {
+            Some(element) => {
+                body.push(element);
+                i = rest;
+            }
+            None => break,
+        }
+    }
+
+    Some(Recovered {
+        lso: Lso { header, body },
+        unread: data.len() - i.len()..data.len(),
+    })
+}
+
+fn read_header(i: &mut &[u8]) -> Option<Header> {
+    if take(i, 2)? != HEADER_VERSION {
+        return None;
+    }
+
+    let length = u32::from_be_bytes(take(i, 4)?.try_into().ok()?);
+
+    if take(i, HEADER_SIGNATURE.len())? != HEADER_SIGNATURE {
+        return None;
+    }
+
+    let name = amf0::read_string(i)?;
+
+    if take(i, HEADER_PADDING.len())? != HEADER_PADDING {
+        return None;
+    }
+
+    let format_version = match take(i, 1)?[0] {
+        FORMAT_VERSION_AMF0 => AMFVersion::AMF0,
+        FORMAT_VERSION_AMF3 => AMFVersion::AMF3,
+        _ => return None,
+    };
+
+    Some(Header {
+        length,
+        name,
+        format_version,
+    })
+}
+
+/// Reads one element of an AMF3 body and the padding after it. `decoder` is shared between the
+/// elements as later ones can refer to strings and classes of earlier ones.
+fn read_amf3_element(i: &mut &[u8], decoder: &mut AMF3Decoder) -> Option<Element> {
+    let name = read_amf3_string(i, decoder)?;
+    let (rest, value) = decoder.parse_single_element(i).ok()?;
+    *i = rest;
+
+    match take(i, 1)? {
+        [PADDING] => Some(Element { name, value }),
+        _ => None,
+    }
+}
+
+/// Reads an AMF3 string, which is either inline or a reference to an earlier one.
+fn read_amf3_string(i: &mut &[u8], decoder: &mut AMF3Decoder) -> Option<String> {
+    let header = read_u29(i)? as usize;
+
+    let bytes = if header & 1 == 0 {
+        decoder.string_reference_table.get(header >> 1)?.clone()
+    } else {
+        let bytes = take(i, header >> 1)?.to_vec();
+        // Empty strings are never referenced so they aren't added to the table
+        if !bytes.is_empty() {
+            decoder.string_reference_table.push(bytes.clone());
+        }
+        bytes
+    };
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Reads an AMF3 variable length integer, up to 29 bits in one to four bytes.
+fn read_u29(i: &mut &[u8]) -> Option<u32> {
+    let mut value = 0;
+
+    for n in 0..4 {
+        let byte = take(i, 1)?[0] as u32;
+
+        // The fourth byte uses all of its bits
+        if n == 3 {
+            return Some(value << 8 | byte);
+        }
+
+        value = value << 7 | (byte & 0x7f);
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
@@ -0,0 +1,78 @@
+//! Replacing many values at once: the text of strings matching a regular expression, or the
+//! numbers a query selects.
+
+use std::borrow::Cow;
+use std::rc::Rc;
+
+use flash_lso::types::{Element, Value};
+use regex::Regex;
+
+use crate::path::ValuePath;
+use crate::query::{self, Query};
+use crate::value;
+
+pub enum Replace {
+    /// Replaces what `regex` matches in strings with `replacement`, which can refer to the groups
+    /// of the match as `$1` or `${name}`.
+    Strings { regex: Regex, replacement: String },
+    /// Sets the numbers `query` selects to `number`. Integers are only set to whole numbers they
+    /// can hold.
+    Numbers { query: Query, number: f64 },
+}
+
+/// A value that changes, with what it becomes.
+#[derive(Clone, Debug)]
+pub struct Replacement {
+    pub path: ValuePath,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl Replace {
+    /// The values in `body` that change, parents before their children.
+    pub fn preview(&self, body: &[Element]) -> Vec<Replacement> {
+        let candidates: Vec<(ValuePath, Cow<'_, Value>)> = match self {
+            Replace::Strings { .. } => query::values(body)
+                .into_iter()
+                .map(|(path, value)| (path, Cow::Borrowed(value)))
+                .collect(),
+            Replace::Numbers { query, .. } => query
+                .run(body)
+                .into_iter()
+                .map(|found| (found.path, Cow::Owned(found.value)))
+                .collect(),
+        };
+
+        candidates
+            .into_iter()
+            .filter_map(|(path, old)| {
+                let new = self.replace(&old)?;
+                Some(Replacement {
+                    path,
+                    old: old.into_owned(),
+                    new,
+                })
+            })
+            .collect()
+    }
+
+    /// The replacement of `value`, if it changes.
+    fn replace(&self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (_, Value::AMF3(inner)) => self.replace(inner).map(|new| Value::AMF3(Rc::new(new))),
+            (Replace::Strings { regex, replacement }, Value::String(string)) => {
+                let new = regex.replace_all(string, replacement.as_str());
+                (new != *string).then(|| Value::String(new.into_owned()))
+            }
+            (Replace::Numbers { number, .. }, Value::Number(old)) => {
+                (old.to_bits() != number.to_bits()).then_some(Value::Number(*number))
+            }
+            (Replace::Numbers { number, .. }, Value::Integer(old)) => {
+                let range = f64::from(value::INTEGER_MIN)..=f64::from(value::INTEGER_MAX);
+                let holds = number.fract() == 0.0 && range.contains(number);
+                (holds && f64::from(*old) != *number).then_some(Value::Integer(*number as i32))
+            }
+            _ => None,
+        }
+    }
+}
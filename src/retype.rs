@@ -0,0 +1,318 @@
+//! Changing the type of a value, converting what it holds.
+//!
+//! Numbers, integers, strings and booleans convert into each other, arrays and vectors into each
+//! other, and objects into ECMA arrays and back. Conversions that lose information succeed with a
+//! warning, values that have no equivalent in the new type are errors.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Element, Value};
+
+use crate::value;
+
+/// Groups of types that convert into each other.
+const GROUPS: [&[&str]; 3] = [
+    &["Number", "Integer", "String", "Bool"],
+    &["StrictArray", "VectorInt", "VectorUInt", "VectorDouble", "VectorObject"],
+    &["Object", "ECMAArray"],
+];
+
+#[derive(Debug)]
+pub enum RetypeError {
+    /// There is no conversion between the types.
+    Incompatible { from: &'static str, to: String },
+    /// The value, or an item of it, has no equivalent in the new type.
+    Unrepresentable(String),
+}
+
+impl fmt::Display for RetypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetypeError::Incompatible { from, to } => write!(f, "{} can't be changed to {}", from, to),
+            RetypeError::Unrepresentable(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for RetypeError {}
+
+/// A value changed to another type.
+pub struct Retyped {
+    pub value: Value,
+    /// What the conversion lost, to show before it is applied.
+    pub warnings: Vec<String>,
+}
+
+/// The types `value`, in a document of `version`, can be changed to. Types the document can't
+/// hold in its place aren't offered, see `value::can_hold`.
+pub fn targets(value: &Value, version: AMFVersion) -> Vec<&'static str> {
+    let type_name = value::type_name(unwrap(value));
+    let holds = |target: &str| {
+        let example = match value {
+            Value::AMF3(_) => value::default_value(target, AMFVersion::AMF3).map(|example| Value::AMF3(Rc::new(example))),
+            _ => value::default_value(target, version),
+        };
+        example.map_or(false, |example| value::can_hold(version, &example))
+    };
+
+    GROUPS
+        .iter()
+        .find(|group| group.contains(&type_name))
+        .map(|group| group.iter().copied().filter(|other| *other != type_name && holds(other)).collect())
+        .unwrap_or_default()
+}
+
+/// Converts `value`, from a document of `version`, to the type called `type_name`.
+pub fn retype(value: &Value, type_name: &str, version: AMFVersion) -> Result<Retyped, RetypeError> {
+    let mut converter = Converter {
+        version,
+        warnings: Vec::new(),
+    };
+
+    let value = match value {
+        // Values stored as AMF3 inside AMF0 files stay that way
+        Value::AMF3(inner) => {
+            converter.version = AMFVersion::AMF3;
+            Value::AMF3(Rc::new(converter.convert(inner, type_name)?))
+        }
+        value => converter.convert(value, type_name)?,
+    };
+    if !value::can_hold(version, &value) {
+        return Err(RetypeError::Unrepresentable(format!("{} values can't be stored in AMF0 files", type_name)));
+    }
+
+    Ok(Retyped {
+        value,
+        warnings: converter.warnings,
+    })
+}
+
+fn unwrap(value: &Value) -> &Value {
+    match value {
+        Value::AMF3(inner) => inner,
+        value => value,
+    }
+}
+
+struct Converter {
+    /// The encoding of the converted value.
+    version: AMFVersion,
+    warnings: Vec<String>,
+}
+
+impl Converter {
+    fn convert(&mut self, value: &Value, to: &str) -> Result<Value, RetypeError> {
+        let converted = match (value, to) {
+            (Value::Number(number), "Integer") => Value::Integer(self.integer(*number, value::INTEGER_MIN, value::INTEGER_MAX)?),
+            (Value::Number(number), "String") => Value::String(number.to_string()),
+            (Value::Number(number), "Bool") => {
+                if *number != 0.0 && *number != 1.0 {
+                    self.warnings.push(format!("{} becomes {}", number, *number != 0.0 && !number.is_nan()));
+                }
+                Value::Bool(*number != 0.0 && !number.is_nan())
+            }
+            (Value::Integer(integer), "Number") => Value::Number(f64::from(*integer)),
+            (Value::Integer(integer), "String") => Value::String(integer.to_string()),
+            (Value::Integer(integer), "Bool") => {
+                if *integer != 0 && *integer != 1 {
+                    self.warnings.push(format!("{} becomes true", integer));
+                }
+                Value::Bool(*integer != 0)
+            }
+            (Value::String(string), "Number") => Value::Number(parse_number(string)?),
+            (Value::String(string), "Integer") => {
+                Value::Integer(self.integer(parse_number(string)?, value::INTEGER_MIN, value::INTEGER_MAX)?)
+            }
+            (Value::String(string), "Bool") => match string.trim().to_ascii_lowercase().as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => {
+                    return Err(RetypeError::Unrepresentable(format!(
+                        "{:?} is neither true nor false",
+                        string
+                    )))
+                }
+            },
+            (Value::Bool(bool), "Number") => Value::Number(if *bool { 1.0 } else { 0.0 }),
+            (Value::Bool(bool), "Integer") => Value::Integer(*bool as i32),
+            (Value::Bool(bool), "String") => Value::String(bool.to_string()),
+            (
+                Value::StrictArray(_)
+                | Value::VectorInt(_, _)
+                | Value::VectorUInt(_, _)
+                | Value::VectorDouble(_, _)
+                | Value::VectorObject(_, _, _),
+                "StrictArray" | "VectorInt" | "VectorUInt" | "VectorDouble" | "VectorObject",
+            ) => self.convert_list(value, to)?,
+            (Value::Object(elements, class_definition), "ECMAArray") => {
+                if let Some(class_definition) = class_definition.as_ref().filter(|class| !class.name.is_empty()) {
+                    self.warnings.push(format!("The class {} is dropped", class_definition.name));
+                }
+                Value::ECMAArray(Vec::new(), elements.clone(), elements.len() as u32)
+            }
+            (Value::ECMAArray(dense, elements, _), "Object") => {
+                if !dense.is_empty() {
+                    self.warnings.push(format!(
+                        "{} dense entries become properties named by their index",
+                        dense.len()
+                    ));
+                }
+
+                let mut properties: Vec<Element> = dense
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| Element {
+                        name: index.to_string(),
+                        value: Rc::clone(value),
+                    })
+                    .collect();
+                properties.extend(elements.iter().cloned());
+
+                Value::Object(properties, value::anonymous_class(self.version))
+            }
+            (value, to) if value::type_name(value) == to => value.clone(),
+            (value, to) => {
+                return Err(RetypeError::Incompatible {
+                    from: value::type_name(value),
+                    to: to.to_string(),
+                })
+            }
+        };
+
+        Ok(converted)
+    }
+
+    /// Converts between arrays and vectors item by item.
+    fn convert_list(&mut self, value: &Value, to: &str) -> Result<Value, RetypeError> {
+        let (items, fixed) = match value {
+            Value::StrictArray(values) => (values.iter().map(|value| value.as_ref().clone()).collect(), false),
+            Value::VectorInt(items, fixed) => (items.iter().map(|item| self.integer_item(f64::from(*item))).collect(), *fixed),
+            Value::VectorUInt(items, fixed) => (items.iter().map(|item| self.integer_item(f64::from(*item))).collect(), *fixed),
+            Value::VectorDouble(items, fixed) => (items.iter().map(|item| Value::Number(*item)).collect(), *fixed),
+            Value::VectorObject(values, _, fixed) => (values.iter().map(|value| value.as_ref().clone()).collect(), *fixed),
+            _ => unreachable!("only called with lists"),
+        };
+        let items: Vec<Value> = items;
+
+        let converted = match to {
+            "StrictArray" => {
+                if fixed {
+                    self.warnings.push("The fixed length is dropped".to_string());
+                }
+                Value::StrictArray(items.into_iter().map(Rc::new).collect())
+            }
+            "VectorInt" => Value::VectorInt(self.numbers(&items, |converter, number| {
+                converter.integer(number, i32::MIN, i32::MAX)
+            })?, fixed),
+            "VectorUInt" => Value::VectorUInt(self.numbers(&items, |converter, number| {
+                converter.integer(number, u32::MIN, u32::MAX)
+            })?, fixed),
+            "VectorDouble" => Value::VectorDouble(self.numbers(&items, |_, number| Ok(number))?, fixed),
+            _ => Value::VectorObject(items.into_iter().map(Rc::new).collect(), "Object".to_string(), fixed),
+        };
+
+        Ok(converted)
+    }
+
+    /// An integer from a numeric vector as an array item, a number if it is too big for an AMF3
+    /// integer or the document can't hold integers.
+    fn integer_item(&self, number: f64) -> Value {
+        match self.version {
+            AMFVersion::AMF3 if (f64::from(value::INTEGER_MIN)..=f64::from(value::INTEGER_MAX)).contains(&number) => {
+                Value::Integer(number as i32)
+            }
+            _ => Value::Number(number),
+        }
+    }
+
+    /// Converts the numeric items of an array with `convert`.
+    fn numbers<T>(
+        &mut self,
+        items: &[Value],
+        mut convert: impl FnMut(&mut Self, f64) -> Result<T, RetypeError>,
+    ) -> Result<Vec<T>, RetypeError> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| match unwrap(item) {
+                Value::Number(number) => convert(self, *number),
+                Value::Integer(integer) => convert(self, f64::from(*integer)),
+                item => Err(RetypeError::Unrepresentable(format!(
+                    "Item {} is a {}, not a number",
+                    index,
+                    value::type_name(item)
+                ))),
+            })
+            .collect()
+    }
+
+    /// Rounds `number` to an integer between `min` and `max`, warning if that changes it.
+    fn integer<T>(&mut self, number: f64, min: T, max: T) -> Result<T, RetypeError>
+    where
+        T: Copy + Into<f64> + TryFrom<i64> + fmt::Display,
+    {
+        if number.is_nan() {
+            return Err(RetypeError::Unrepresentable("NaN has no integer equivalent".to_string()));
+        }
+
+        let integer = number.round().max(min.into()).min(max.into());
+        if integer != number {
+            self.warnings.push(format!("{} becomes {}", number, integer));
+        }
+
+        // In range after clamping, so this can't fail
+        Ok(T::try_from(integer as i64).unwrap_or(min))
+    }
+}
+
+fn parse_number(string: &str) -> Result<f64, RetypeError> {
+    string
+        .trim()
+        .parse()
+        .map_err(|_| RetypeError::Unrepresentable(format!("{:?} is not a number", string)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_are_types_the_document_can_hold() {
+        assert_eq!(targets(&Value::Number(1.0), AMFVersion::AMF3), ["Integer", "String", "Bool"]);
+        assert_eq!(targets(&Value::Number(1.0), AMFVersion::AMF0), ["String", "Bool"]);
+        assert_eq!(targets(&Value::StrictArray(Vec::new()), AMFVersion::AMF0), Vec::<&str>::new());
+
+        let object = Value::Object(Vec::new(), value::anonymous_class(AMFVersion::AMF3));
+        assert_eq!(targets(&object, AMFVersion::AMF3), ["ECMAArray"]);
+        assert_eq!(targets(&Value::AMF3(Rc::new(object)), AMFVersion::AMF0), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn retype_converts_with_warnings() {
+        let retyped = retype(&Value::Number(2.5), "Integer", AMFVersion::AMF3).unwrap();
+        assert_eq!(retyped.value, Value::Integer(3));
+        assert_eq!(retyped.warnings, ["2.5 becomes 3"]);
+
+        let retyped = retype(&Value::String(" 7 ".to_string()), "Number", AMFVersion::AMF0).unwrap();
+        assert_eq!(retyped.value, Value::Number(7.0));
+        assert!(retyped.warnings.is_empty());
+
+        assert!(retype(&Value::String("seven".to_string()), "Number", AMFVersion::AMF0).is_err());
+        assert!(retype(&Value::Number(f64::NAN), "Integer", AMFVersion::AMF3).is_err());
+    }
+
+    #[test]
+    fn retype_rejects_amf3_values_amf0_can_not_hold() {
+        // AMF0 files only hold AMF3 objects
+        let object = Value::Object(vec![Element::new("count", Value::Integer(5))], value::anonymous_class(AMFVersion::AMF3));
+        assert!(retype(&Value::AMF3(Rc::new(object)), "ECMAArray", AMFVersion::AMF0).is_err());
+    }
+
+    #[test]
+    fn retype_rejects_types_the_document_can_not_hold() {
+        assert!(retype(&Value::Number(2.0), "Integer", AMFVersion::AMF0).is_err());
+        assert_eq!(retype(&Value::Number(2.0), "Integer", AMFVersion::AMF3).unwrap().value, Value::Integer(2));
+    }
+}
@@ -0,0 +1,544 @@
+//! Scripts editing the body of a document, written in Rhai (https://rhai.rs).
+//!
+//! Values are addressed by their path as a string, `""` being the body itself. Scripts can call:
+//!
+//! - `get(path)`: the value, numbers, integers, strings and booleans as Rhai values, null and
+//!   undefined as `()` and anything else as a `Value` that can be stored elsewhere with `set`.
+//! - `exists(path)`, `kind(path)`: whether there is a value, and the name of its type.
+//! - `children(path)`: the paths of the values below the value, to iterate over. Items of vectors
+//!   of numbers have no paths and aren't listed.
+//! - `set(path, value)`: replaces the value. Rhai numbers keep the type of the value they replace.
+//! - `insert(parent, name, value)`: adds an element to the body, an object or an ECMA array.
+//! - `insert(parent, index, value)`: inserts an entry into an array or vector.
+//! - `delete(path)`: removes an element or entry.
+//! - `number(x)`, `integer(x)`, `date(millis)`, `xml(text)`, `undefined()` and `new_value(type)`
+//!   create values of a given type. Rhai arrays become strict arrays and maps become objects,
+//!   their properties sorted by name.
+//!
+//! Storing a value the document can't hold at a path fails the script, such as an integer among
+//! the values of an AMF0 file that aren't stored as AMF3.
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Element, Value};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, FLOAT, INT};
+
+use crate::document;
+use crate::history::Edit;
+use crate::path::{Segment, ValuePath};
+use crate::query;
+use crate::value;
+
+/// Operations a script can run before it is stopped, so that endless loops don't hang the editor.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The outcome of running a script.
+pub struct ScriptRun {
+    /// What the script printed, line by line.
+    pub output: Vec<String>,
+    /// The body as the script left it, or why the script failed.
+    pub result: Result<Vec<Element>, String>,
+}
+
+/// Runs `script` on a copy of `body`, from a document of `version`.
+pub fn run(script: &str, body: &[Element], version: AMFVersion) -> ScriptRun {
+    let document = Rc::new(RefCell::new(Document {
+        body: body.to_vec(),
+        version,
+    }));
+    let output = Rc::new(RefCell::new(Vec::new()));
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    {
+        let output = Rc::clone(&output);
+        engine.on_print(move |text| output.borrow_mut().push(text.to_string()));
+    }
+    register(&mut engine, &document);
+
+    let result = engine
+        .run(script)
+        .map(|()| std::mem::take(&mut document.borrow_mut().body))
+        .map_err(|error| error.to_string());
+
+    let output = output.borrow().clone();
+    ScriptRun { output, result }
+}
+
+/// A value of a type Rhai has no equivalent for, or created with a given type.
+#[derive(Clone)]
+struct ScriptValue(Value);
+
+type Failure = Box<EvalAltResult>;
+
+fn register(engine: &mut Engine, document: &Rc<RefCell<Document>>) {
+    engine
+        .register_type_with_name::<ScriptValue>("Value")
+        .register_fn("to_string", |value: &mut ScriptValue| value::describe(&value.0))
+        .register_fn("to_debug", |value: &mut ScriptValue| value::describe(&value.0));
+
+    let shared = Rc::clone(document);
+    engine.register_fn("get", move |path: &str| -> Result<Dynamic, Failure> {
+        let document = shared.borrow();
+        Ok(to_dynamic(document.value(&parse_path(path)?)?))
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("exists", move |path: &str| -> Result<bool, Failure> {
+        let path = parse_path(path)?;
+        Ok(path.is_root() || path.get(&shared.borrow().body).is_some())
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("kind", move |path: &str| -> Result<String, Failure> {
+        let path = parse_path(path)?;
+        if path.is_root() {
+            return Ok("Body".to_string());
+        }
+        Ok(value::type_name(shared.borrow().value(&path)?).to_string())
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("children", move |path: &str| -> Result<Array, Failure> {
+        shared.borrow().children(&parse_path(path)?)
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("set", move |path: &str, value: Dynamic| -> Result<(), Failure> {
+        shared.borrow_mut().set(&parse_path(path)?, value)
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("insert", move |parent: &str, name: &str, value: Dynamic| -> Result<(), Failure> {
+        shared.borrow_mut().insert_element(&parse_path(parent)?, name, value)
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("insert", move |parent: &str, index: INT, value: Dynamic| -> Result<(), Failure> {
+        shared.borrow_mut().insert_entry(&parse_path(parent)?, index, value)
+    });
+    let shared = Rc::clone(document);
+    engine.register_fn("delete", move |path: &str| -> Result<(), Failure> {
+        shared.borrow_mut().delete(&parse_path(path)?)
+    });
+
+    let version = document.borrow().version;
+    engine
+        .register_fn("number", |number: FLOAT| ScriptValue(Value::Number(number)))
+        .register_fn("number", |number: INT| ScriptValue(Value::Number(number as f64)))
+        .register_fn("integer", |integer: INT| -> Result<ScriptValue, Failure> {
+            Ok(ScriptValue(Value::Integer(to_integer(integer)?)))
+        })
+        .register_fn("date", |millis: FLOAT| ScriptValue(Value::Date(millis, None)))
+        .register_fn("date", |millis: INT| ScriptValue(Value::Date(millis as f64, None)))
+        .register_fn("xml", |text: &str| ScriptValue(Value::XML(text.to_string(), true)))
+        .register_fn("undefined", || ScriptValue(Value::Undefined))
+        .register_fn("new_value", move |type_name: &str| -> Result<ScriptValue, Failure> {
+            value::default_value(type_name, version)
+                .map(ScriptValue)
+                .ok_or_else(|| format!("values of type {} can't be created", type_name).into())
+        });
+}
+
+/// `""` for the body, or a path like `player.inventory[3]`.
+fn parse_path(path: &str) -> Result<ValuePath, Failure> {
+    if path.is_empty() {
+        return Ok(ValuePath::default());
+    }
+
+    path.parse()
+        .map_err(|error| format!("`{}` is not a valid path: {}", path, error).into())
+}
+
+fn to_integer(integer: INT) -> Result<i32, Failure> {
+    if (INT::from(value::INTEGER_MIN)..=INT::from(value::INTEGER_MAX)).contains(&integer) {
+        Ok(integer as i32)
+    } else {
+        Err(format!("{} is too large for an Integer", integer).into())
+    }
+}
+
+fn unwrap(value: &Value) -> &Value {
+    match value {
+        Value::AMF3(inner) => inner,
+        value => value,
+    }
+}
+
+fn unwrap_mut(value: &mut Value) -> &mut Value {
+    match value {
+        Value::AMF3(inner) => Rc::make_mut(inner),
+        value => value,
+    }
+}
+
+fn to_dynamic(value: &Value) -> Dynamic {
+    match unwrap(value) {
+        Value::Number(number) => Dynamic::from(*number),
+        Value::Integer(integer) => Dynamic::from(INT::from(*integer)),
+        Value::String(string) => Dynamic::from(string.clone()),
+        Value::Bool(bool) => Dynamic::from(*bool),
+        Value::Null | Value::Undefined => Dynamic::UNIT,
+        _ => Dynamic::from(ScriptValue(value.clone())),
+    }
+}
+
+/// The body being edited by a script.
+struct Document {
+    body: Vec<Element>,
+    version: AMFVersion,
+}
+
+impl Document {
+    fn value(&self, path: &ValuePath) -> Result<&Value, Failure> {
+        path.get(&self.body)
+            .ok_or_else(|| format!("nothing found at {}", path).into())
+    }
+
+    /// The elements of the body or of the value at `parent`, if it has any.
+    fn elements(&self, parent: &ValuePath) -> Result<&[Element], Failure> {
+        if parent.is_root() {
+            return Ok(&self.body);
+        }
+
+        match unwrap(self.value(parent)?) {
+            Value::Object(elements, _) | Value::ECMAArray(_, elements, _) | Value::Custom(_, elements, _) => {
+                Ok(elements)
+            }
+            value => Err(format!("{} is a {}, which has no elements", parent, value::type_name(value)).into()),
+        }
+    }
+
+    fn children(&self, path: &ValuePath) -> Result<Array, Failure> {
+        let children = if path.is_root() {
+            query::element_children(&self.body)
+        } else {
+            query::value_children(self.value(path)?)
+        };
+
+        Ok(children
+            .into_iter()
+            .map(|(segment, _)| path.join(segment))
+            .map(|child| Dynamic::from(child.to_string()))
+            .collect())
+    }
+
+    /// The version values inserted into `parent` are encoded with.
+    fn version_in(&self, parent: &ValuePath) -> AMFVersion {
+        match parent.get(&self.body) {
+            Some(Value::AMF3(_)) => AMFVersion::AMF3,
+            _ => document::version_at(&self.body, self.version, parent),
+        }
+    }
+
+    fn set(&mut self, path: &ValuePath, value: Dynamic) -> Result<(), Failure> {
+        let old = self.value(path)?;
+        let version = document::version_at(&self.body, self.version, path);
+        let value = self.to_value(value, Some(old), version)?;
+        let value = store_at(path, version, Some(old), value)?;
+        path.set(&mut self.body, value);
+        Ok(())
+    }
+
+    fn insert_element(&mut self, parent: &ValuePath, name: &str, value: Dynamic) -> Result<(), Failure> {
+        let elements = self.elements(parent)?;
+        if elements.iter().any(|element| element.name == name) {
+            return Err(format!("there already is an element called {:?} in {}", name, parent).into());
+        }
+
+        let version = self.version_in(parent);
+        let value = self.to_value(value, None, version)?;
+        let edit = Edit::Insert {
+            parent: parent.clone(),
+            index: elements.len(),
+            element: Element::new(name, store_at(&parent.child(name), version, None, value)?),
+        };
+        edit.apply_to_body(&mut self.body);
+        Ok(())
+    }
+
+    fn insert_entry(&mut self, parent: &ValuePath, index: INT, value: Dynamic) -> Result<(), Failure> {
+        let version = self.version_in(parent);
+        let value = self.to_value(value, None, version)?;
+        let list = match parent.get_mut(&mut self.body) {
+            Some(list) => unwrap_mut(list),
+            None => return Err(format!("nothing found at {}", parent).into()),
+        };
+        let index = match usize::try_from(index) {
+            Ok(index) if index <= list_length(list).unwrap_or(0) => index,
+            _ => return Err(format!("there is no index {} in {}", index, parent).into()),
+        };
+        let value = store_at(&parent.index(index), version, None, value)?;
+        let number = || match unwrap(&value) {
+            Value::Number(number) => Ok(*number),
+            Value::Integer(integer) => Ok(f64::from(*integer)),
+            value => Err(Failure::from(format!("vectors of numbers can't hold a {}", value::type_name(value)))),
+        };
+        let fixed = || Err(format!("{} has a fixed length", parent).into());
+
+        match list {
+            Value::StrictArray(values) => values.insert(index, Rc::new(value)),
+            Value::ECMAArray(..) if version == AMFVersion::AMF0 => return Err(dense_in_amf0(parent)),
+            // The declared length only counts the named entries
+            Value::ECMAArray(values, _, _) => values.insert(index, Rc::new(value)),
+            Value::VectorObject(_, _, true)
+            | Value::VectorInt(_, true)
+            | Value::VectorUInt(_, true)
+            | Value::VectorDouble(_, true) => return fixed(),
+            Value::VectorObject(values, _, false) => values.insert(index, Rc::new(value)),
+            Value::VectorInt(items, false) => items.insert(index, to_whole(number()?)?),
+            Value::VectorUInt(items, false) => items.insert(index, to_whole(number()?)?),
+            Value::VectorDouble(items, false) => items.insert(index, number()?),
+            list => return Err(format!("{} is a {}, which has no entries", parent, value::type_name(list)).into()),
+        }
+
+        Ok(())
+    }
+
+    fn delete(&mut self, path: &ValuePath) -> Result<(), Failure> {
+        let parent = path.parent().ok_or_else(|| Failure::from("the body can't be deleted"))?;
+
+        match path.last() {
+            Some(Segment::Name(name)) => {
+                let elements = self.elements(&parent)?;
+                let index = elements
+                    .iter()
+                    .position(|element| &element.name == name)
+                    .ok_or_else(|| Failure::from(format!("nothing found at {}", path)))?;
+                let edit = Edit::Delete {
+                    parent,
+                    index,
+                    element: elements[index].clone(),
+                };
+                edit.apply_to_body(&mut self.body);
+            }
+            Some(Segment::Index(index)) => {
+                let index = *index;
+                let version = self.version_in(&parent);
+                let missing = || format!("nothing found at {}", path).into();
+                let list = match parent.get_mut(&mut self.body) {
+                    Some(list) => unwrap_mut(list),
+                    None => return Err(missing()),
+                };
+                if index >= list_length(list).unwrap_or(0) {
+                    return Err(missing());
+                }
+
+                match list {
+                    Value::ECMAArray(..) if version == AMFVersion::AMF0 => return Err(dense_in_amf0(&parent)),
+                    Value::StrictArray(values)
+                    | Value::ECMAArray(values, _, _)
+                    | Value::VectorObject(values, _, false) => {
+                        values.remove(index);
+                    }
+                    Value::Dictionary(pairs, _) => {
+                        pairs.remove(index);
+                    }
+                    Value::VectorInt(items, false) => {
+                        items.remove(index);
+                    }
+                    Value::VectorUInt(items, false) => {
+                        items.remove(index);
+                    }
+                    Value::VectorDouble(items, false) => {
+                        items.remove(index);
+                    }
+                    _ => return Err(format!("{} has a fixed length", parent).into()),
+                }
+            }
+            None => unreachable!("only the body has no last segment"),
+        }
+
+        Ok(())
+    }
+
+    /// Converts a value from a script to a value stored with `version`. Rhai numbers take the type
+    /// of `like`, the value they replace, if it is a number.
+    fn to_value(&self, dynamic: Dynamic, like: Option<&Value>, version: AMFVersion) -> Result<Value, Failure> {
+        let like = like.map(unwrap);
+
+        let value = if dynamic.is::<ScriptValue>() {
+            return Ok(dynamic.cast::<ScriptValue>().0);
+        } else if let Some(number) = dynamic.clone().try_cast::<FLOAT>() {
+            match like {
+                Some(Value::Integer(_)) if number.fract() == 0.0 => Value::Integer(to_integer(number as INT)?),
+                _ => Value::Number(number),
+            }
+        } else if let Some(integer) = dynamic.clone().try_cast::<INT>() {
+            match like {
+                Some(Value::Integer(_)) => Value::Integer(to_integer(integer)?),
+                None if version == AMFVersion::AMF3 => match to_integer(integer) {
+                    Ok(integer) => Value::Integer(integer),
+                    Err(_) => Value::Number(integer as f64),
+                },
+                _ => Value::Number(integer as f64),
+            }
+        } else if let Some(bool) = dynamic.clone().try_cast::<bool>() {
+            Value::Bool(bool)
+        } else if dynamic.is_string() {
+            let string = dynamic.into_string().unwrap_or_default();
+            match like {
+                Some(Value::XML(_, flag)) => Value::XML(string, *flag),
+                _ => Value::String(string),
+            }
+        } else if dynamic.is_unit() {
+            match like {
+                Some(Value::Undefined) => Value::Undefined,
+                _ => Value::Null,
+            }
+        } else if let Some(items) = dynamic.clone().try_cast::<Array>() {
+            let values = items
+                .into_iter()
+                .map(|item| self.to_value(item, None, version).map(Rc::new))
+                .collect::<Result<_, _>>()?;
+            Value::StrictArray(values)
+        } else if let Some(map) = dynamic.clone().try_cast::<Map>() {
+            let elements = map
+                .into_iter()
+                .map(|(name, value)| Ok(Element::new(name.as_str(), self.to_value(value, None, version)?)))
+                .collect::<Result<_, Failure>>()?;
+            Value::Object(elements, value::anonymous_class(version))
+        } else {
+            return Err(format!("a {} can't be stored in a document", dynamic.type_name()).into());
+        };
+
+        Ok(value)
+    }
+}
+
+/// `value` as it is stored at `path`, see `document::store_at`.
+fn store_at(path: &ValuePath, version: AMFVersion, old: Option<&Value>, value: Value) -> Result<Value, Failure> {
+    document::store_at(path, version, old, value).map_err(|error| error.to_string().into())
+}
+
+/// AMF0 only writes the named entries of ECMA arrays, so numbered ones can't be edited.
+fn dense_in_amf0(parent: &ValuePath) -> Failure {
+    format!("{} is an ECMA array in an AMF0 file, which only keeps its named entries", parent).into()
+}
+
+/// The number of entries of an array, vector or dictionary.
+fn list_length(value: &Value) -> Option<usize> {
+    let length = match value {
+        Value::StrictArray(values) | Value::ECMAArray(values, _, _) | Value::VectorObject(values, _, _) => values.len(),
+        Value::VectorInt(items, _) => items.len(),
+        Value::VectorUInt(items, _) => items.len(),
+        Value::VectorDouble(items, _) => items.len(),
+        Value::Dictionary(pairs, _) => pairs.len(),
+        _ => return None,
+    };
+
+    Some(length)
+}
+
+/// `number` as an item of an integer vector.
+fn to_whole<T: TryFrom<i64>>(number: f64) -> Result<T, Failure> {
+    if number.fract() == 0.0 {
+        if let Ok(integer) = T::try_from(number as i64) {
+            return Ok(integer);
+        }
+    }
+
+    Err(format!("{} can't be stored in this vector", number).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flash_lso::types::Lso;
+
+    use crate::custom::CustomClasses;
+    use crate::document::SolDocument;
+
+    fn amf3_object(elements: Vec<Element>) -> Value {
+        Value::AMF3(Rc::new(Value::Object(elements, value::anonymous_class(AMFVersion::AMF3))))
+    }
+
+    fn run_on(script: &str, body: &[Element], version: AMFVersion) -> Result<Vec<Element>, String> {
+        run(script, body, version).result
+    }
+
+    #[test]
+    fn amf0_documents_reject_amf3_only_values() {
+        let body = vec![Element::new("lvl", Value::Number(1.0))];
+
+        for script in &[
+            r#"set("lvl", new_value("ByteArray"));"#,
+            r#"insert("", "list", [integer(3)]);"#,
+        ] {
+            let error = run_on(script, &body, AMFVersion::AMF0).unwrap_err();
+            assert!(error.contains("can't be stored"), "{}: {}", script, error);
+        }
+
+        // Rhai integers become numbers
+        let body = run_on(r#"insert("", "new", 3); insert("", "n", integer(4));"#, &body, AMFVersion::AMF0).unwrap();
+        assert_eq!(body[1].value(), &Value::Number(3.0));
+        assert_eq!(body[2].value(), &Value::Number(4.0));
+    }
+
+    #[test]
+    fn values_inside_amf3_objects_are_not_wrapped() {
+        let body = vec![Element::new("w", amf3_object(vec![Element::new("count", Value::Integer(1))]))];
+        let script = r#"
+            set("w.count", 2);
+            insert("w", "bytes", new_value("ByteArray"));
+            insert("w", "n", integer(3));
+        "#;
+
+        let body = run_on(script, &body, AMFVersion::AMF0).unwrap();
+        let expected = amf3_object(vec![
+            Element::new("count", Value::Integer(2)),
+            Element::new("bytes", Value::ByteArray(Vec::new())),
+            Element::new("n", Value::Integer(3)),
+        ]);
+        assert_eq!(body, vec![Element::new("w", expected)]);
+    }
+
+    #[test]
+    fn set_keeps_amf3_wrappers() {
+        let body = vec![Element::new("w", amf3_object(Vec::new()))];
+
+        let body = run_on(r#"set("w", #{a: 1});"#, &body, AMFVersion::AMF0).unwrap();
+        assert!(matches!(body[0].value(), Value::AMF3(_)));
+    }
+
+    #[test]
+    fn children_can_be_addressed() {
+        let body = vec![
+            Element::new("v", Value::AMF3(Rc::new(Value::VectorInt(vec![1, 2], false)))),
+            Element::new("a", Value::StrictArray(vec![Rc::new(Value::Null)])),
+        ];
+        let script = r#"for path in children("") { for child in children(path) { print(child); } }"#;
+
+        let run = run(script, &body, AMFVersion::AMF0);
+        assert!(run.result.is_ok());
+        assert_eq!(run.output, ["a[0]"]);
+    }
+
+    #[test]
+    fn numbered_ecma_array_entries_are_saved() {
+        let map = Value::ECMAArray(vec![Rc::new(Value::Number(1.0))], vec![Element::new("x", Value::Null)], 1);
+        let body = vec![Element::new("map", map)];
+        let script = r#"insert("map", 0, "a"); insert("map", 2, "b"); delete("map[1]");"#;
+
+        let body = run_on(script, &body, AMFVersion::AMF3).unwrap();
+        let expected = Value::ECMAArray(
+            vec![Rc::new(Value::String("a".to_string())), Rc::new(Value::String("b".to_string()))],
+            vec![Element::new("x", Value::Null)],
+            1,
+        );
+        assert_eq!(body[0].value(), &expected);
+
+        let classes = Rc::new(CustomClasses::default());
+        let mut document = SolDocument::new(Lso::new(body, "test", AMFVersion::AMF3), None, Rc::clone(&classes));
+        let reread = classes.parse(&document.serialize().unwrap()).unwrap();
+        assert_eq!(reread.body[0].value(), &expected);
+    }
+
+    #[test]
+    fn amf0_documents_reject_numbered_ecma_array_entries() {
+        let map = Value::ECMAArray(vec![Rc::new(Value::Number(1.0))], Vec::new(), 0);
+        let body = vec![Element::new("map", map)];
+
+        for script in &[r#"insert("map", 0, 2);"#, r#"delete("map[0]");"#] {
+            let error = run_on(script, &body, AMFVersion::AMF0).unwrap_err();
+            assert!(error.contains("only keeps its named entries"), "{}: {}", script, error);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Finding values by the name of their element, their contents, their type or the range their
+//! number is in.
+
+use flash_lso::types::{Element, Value};
+use regex::{Regex, RegexBuilder};
+
+use crate::path::{Segment, ValuePath};
+use crate::query;
+use crate::value;
+
+/// Text to look for in names and values.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Substring { text: String, case_sensitive: bool },
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn substring(text: &str, case_sensitive: bool) -> Self {
+        let text = if case_sensitive { text.to_string() } else { text.to_lowercase() };
+        Pattern::Substring { text, case_sensitive }
+    }
+
+    pub fn regex(pattern: &str, case_sensitive: bool) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(pattern).case_insensitive(!case_sensitive).build()?;
+        Ok(Pattern::Regex(regex))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Pattern::Substring { text: pattern, case_sensitive: true } => text.contains(pattern.as_str()),
+            Pattern::Substring { text: pattern, case_sensitive: false } => text.to_lowercase().contains(pattern.as_str()),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// What to look for. A value is found when it meets every criterion that is set.
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub pattern: Option<Pattern>,
+    /// Look for the pattern in the names of elements.
+    pub in_names: bool,
+    /// Look for the pattern in the text of strings, numbers, booleans and dates.
+    pub in_values: bool,
+    /// Only numbers and integers at least this large.
+    pub min: Option<f64>,
+    /// Only numbers and integers at most this large.
+    pub max: Option<f64>,
+    /// Only values of this type, as named by `value::type_name`.
+    pub type_name: Option<&'static str>,
+}
+
+/// A value that was found.
+#[derive(Clone, Debug)]
+pub struct Hit {
+    pub path: ValuePath,
+    pub value: Value,
+    /// The pattern was found in the name of the element rather than in the value.
+    pub in_name: bool,
+}
+
+impl Search {
+    /// Whether no criterion is set, which finds nothing rather than everything.
+    pub fn is_empty(&self) -> bool {
+        self.pattern.is_none() && self.min.is_none() && self.max.is_none() && self.type_name.is_none()
+    }
+
+    /// The values in `body` that were found, parents before their children.
+    pub fn run(&self, body: &[Element]) -> Vec<Hit> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        query::values(body)
+            .into_iter()
+            .filter_map(|(path, value)| {
+                let name = match path.last() {
+                    Some(Segment::Name(name)) => Some(name.as_str()),
+                    _ => None,
+                };
+                let in_name = self.matches(name, value)?;
+
+                Some(Hit {
+                    value: value.clone(),
+                    path,
+                    in_name,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the value of the element called `name` is found, and if so whether the pattern
+    /// was only found in its name.
+    fn matches(&self, name: Option<&str>, value: &Value) -> Option<bool> {
+        let value = match value {
+            Value::AMF3(inner) => inner,
+            value => value,
+        };
+
+        if let Some(type_name) = self.type_name {
+            if value::type_name(value) != type_name {
+                return None;
+            }
+        }
+
+        if self.min.is_some() || self.max.is_some() {
+            let number = match value {
+                Value::Number(number) => *number,
+                Value::Integer(integer) => f64::from(*integer),
+                _ => return None,
+            };
+
+            let range = self.min.unwrap_or(f64::NEG_INFINITY)..=self.max.unwrap_or(f64::INFINITY);
+            if !range.contains(&number) {
+                return None;
+            }
+        }
+
+        let pattern = match &self.pattern {
+            Some(pattern) => pattern,
+            None => return Some(false),
+        };
+
+        let in_name = self.in_names && matches!(name, Some(name) if pattern.is_match(name));
+        let in_value = self.in_values && matches!(searchable_text(value), Some(text) if pattern.is_match(&text));
+
+        match (in_name, in_value) {
+            (_, true) => Some(false),
+            (true, false) => Some(true),
+            (false, false) => None,
+        }
+    }
+}
+
+/// The text of values whose contents can be searched, containers and bytes have none.
+fn searchable_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(_) | Value::XML(_, _) | Value::Number(_) | Value::Integer(_) | Value::Bool(_) | Value::Date(_, _) => {
+            value::format_scalar(value)
+        }
+        _ => None,
+    }
+}
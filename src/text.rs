@@ -0,0 +1,869 @@
+//! Readable YAML and TOML forms of documents, for reviewing saves by hand.
+//!
+//! A document is a mapping with the header fields and the body:
+//!
+//! ```yaml
+//! name: savegame
+//! version: AMF3
+//! body:
+//!   score: 1200
+//!   player:
+//!     name: Alice
+//!     health: 0.75
+//!   unlocked: [1, 2, 3]
+//! ```
+//!
+//! Values that YAML can hold directly are written as they are: numbers as floats, AMF3 integers
+//! as integers, bools, strings, `null`, strict arrays as sequences and anonymous objects as
+//! mappings. Any other value is a mapping tagged with a `$type` key, whose other keys follow the
+//! JSON form described in `json`, for example `{ $type: Date, value: 2021-10-18T13:37:00.000Z }`.
+//! Fields that are empty, `false` or missing in the JSON form are left out. Elements are written
+//! as a mapping, or as a sequence of single entry mappings if some of them share a name.
+//!
+//! Text written here reads back to the same document, so an unedited export is written back to
+//! the identical file. Integers typed into an AMF0 document become numbers, as AMF0 has no
+//! integers.
+//!
+//! TOML is written with the same structure, with nested mappings as inline tables so that
+//! properties keep their order. TOML has no null, so null values are written as
+//! `{ "$type" = "Null" }`.
+
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use flash_lso::types::{AMFVersion, Attribute, ClassDefinition, Element, Header, Lso, Value};
+use serde_yaml::{Mapping, Number};
+
+use crate::date::DateTime;
+use crate::json::{self, JsonError};
+use crate::path::ValuePath;
+use crate::value;
+
+type Yaml = serde_yaml::Value;
+
+/// The key holding the type of values that aren't written as plain YAML.
+const TYPE_KEY: &str = "$type";
+
+/// A text format documents can be exported to and imported from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::Yaml, Format::Toml];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["json"],
+            Format::Yaml => &["yaml", "yml"],
+            Format::Toml => &["toml"],
+        }
+    }
+
+    /// The format of a file, worked out from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn write(self, lso: &Lso) -> String {
+        match self {
+            Format::Json => json::to_string(lso),
+            Format::Yaml => to_yaml(lso),
+            Format::Toml => to_toml(lso),
+        }
+    }
+
+    pub fn read(self, text: &str) -> Result<Lso, TextError> {
+        match self {
+            Format::Json => json::from_str(text).map_err(TextError::from),
+            Format::Yaml => from_yaml(text),
+            Format::Toml => from_toml(text),
+        }
+    }
+}
+
+/// Why text couldn't be read as a document.
+#[derive(Debug)]
+pub enum TextError {
+    Syntax(String),
+    /// The text doesn't describe a document at the value at `path`, the root if it is empty.
+    Schema { path: ValuePath, message: String },
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Syntax(message) => f.write_str(message),
+            TextError::Schema { path, message } if *path == ValuePath::default() => f.write_str(message),
+            TextError::Schema { path, message } => write!(f, "{} at {}", message, path),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl From<JsonError> for TextError {
+    fn from(error: JsonError) -> Self {
+        match error {
+            JsonError::Syntax(error) => TextError::Syntax(error.to_string()),
+            JsonError::Schema { path, message } => TextError::Schema { path, message },
+        }
+    }
+}
+
+pub fn to_yaml(lso: &Lso) -> String {
+    // Serializing a `serde_yaml::Value` can't fail
+    serde_yaml::to_string(&document_to_yaml(lso)).unwrap()
+}
+
+pub fn from_yaml(text: &str) -> Result<Lso, TextError> {
+    let yaml = serde_yaml::from_str(text).map_err(|error| TextError::Syntax(error.to_string()))?;
+    document_from_yaml(&yaml)
+}
+
+pub fn to_toml(lso: &Lso) -> String {
+    let document = match document_to_yaml(lso) {
+        Yaml::Mapping(document) => document,
+        _ => unreachable!(),
+    };
+
+    let mut text = String::new();
+    for (key, value) in &document {
+        match value {
+            // Only the body can be a mapping, it is written last as its own table
+            Yaml::Mapping(body) => {
+                writeln!(text, "\n[{}]", toml_key(key)).unwrap();
+                for (key, value) in body {
+                    writeln!(text, "{} = {}", toml_key(key), toml_value(value)).unwrap();
+                }
+            }
+            _ => writeln!(text, "{} = {}", toml_key(key), toml_value(value)).unwrap(),
+        }
+    }
+    text
+}
+
+pub fn from_toml(text: &str) -> Result<Lso, TextError> {
+    let toml: toml::Value = text.parse().map_err(|error: toml::de::Error| TextError::Syntax(error.to_string()))?;
+    document_from_yaml(&toml_to_yaml(toml))
+}
+
+/// Converts TOML to the YAML it stands for. Serializing a `toml::Value` would move plain values
+/// before tables, changing the order of the properties.
+fn toml_to_yaml(toml: toml::Value) -> Yaml {
+    match toml {
+        toml::Value::String(string) => Yaml::String(string),
+        toml::Value::Integer(integer) => Yaml::Number(Number::from(integer)),
+        toml::Value::Float(float) => Yaml::Number(Number::from(float)),
+        toml::Value::Boolean(bool) => Yaml::Bool(bool),
+        // Lets dates be typed without quotes
+        toml::Value::Datetime(date) => Yaml::String(date.to_string()),
+        toml::Value::Array(values) => values.into_iter().map(toml_to_yaml).collect(),
+        toml::Value::Table(table) => Yaml::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Yaml::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn toml_key(key: &Yaml) -> String {
+    let key = key.as_str().unwrap_or_default();
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+fn toml_string(string: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in string.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Writes `yaml` as a single line TOML value.
+fn toml_value(yaml: &Yaml) -> String {
+    match yaml {
+        Yaml::Null => format!("{{ {} = \"Null\" }}", toml_string(TYPE_KEY)),
+        Yaml::Bool(bool) => bool.to_string(),
+        Yaml::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() => {
+                if float.is_nan() {
+                    "nan".to_string()
+                } else if float.is_infinite() {
+                    if float > 0.0 { "inf" } else { "-inf" }.to_string()
+                } else {
+                    // Debug always writes a fraction or an exponent, so it reads back as a float
+                    format!("{:?}", float)
+                }
+            }
+            _ => number.to_string(),
+        },
+        Yaml::String(string) => toml_string(string),
+        Yaml::Sequence(values) => {
+            let values: Vec<String> = values.iter().map(toml_value).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Yaml::Mapping(mapping) if mapping.is_empty() => "{}".to_string(),
+        Yaml::Mapping(mapping) => {
+            let entries: Vec<String> = mapping
+                .iter()
+                .map(|(key, value)| format!("{} = {}", toml_key(key), toml_value(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+    }
+}
+
+fn document_to_yaml(lso: &Lso) -> Yaml {
+    let version = match lso.header.format_version {
+        AMFVersion::AMF0 => "AMF0",
+        AMFVersion::AMF3 => "AMF3",
+    };
+
+    let mut mapping = Mapping::new();
+    mapping.insert("name".into(), lso.header.name.as_str().into());
+    mapping.insert("version".into(), version.into());
+    mapping.insert("body".into(), elements_to_yaml(&lso.body, lso.header.format_version));
+    Yaml::Mapping(mapping)
+}
+
+fn has_unique_names(elements: &[Element]) -> bool {
+    let mut names = HashSet::new();
+    elements.iter().all(|element| names.insert(element.name.as_str()))
+}
+
+fn elements_to_yaml(elements: &[Element], version: AMFVersion) -> Yaml {
+    if has_unique_names(elements) {
+        let mut mapping = Mapping::new();
+        for element in elements {
+            mapping.insert(element.name.as_str().into(), value_to_yaml(element.value(), version));
+        }
+        Yaml::Mapping(mapping)
+    } else {
+        elements
+            .iter()
+            .map(|element| {
+                let mut mapping = Mapping::new();
+                mapping.insert(element.name.as_str().into(), value_to_yaml(element.value(), version));
+                Yaml::Mapping(mapping)
+            })
+            .collect()
+    }
+}
+
+fn values_to_yaml(values: &[Rc<Value>], version: AMFVersion) -> Yaml {
+    values.iter().map(|value| value_to_yaml(value, version)).collect()
+}
+
+fn class_to_yaml(class_definition: &ClassDefinition) -> Yaml {
+    if class_definition.attributes.is_empty() && class_definition.static_properties.is_empty() {
+        return class_definition.name.as_str().into();
+    }
+
+    let mut mapping = Mapping::new();
+    mapping.insert("name".into(), class_definition.name.as_str().into());
+    if !class_definition.attributes.is_empty() {
+        let attributes: Vec<Yaml> = class_definition
+            .attributes
+            .iter()
+            .map(|attribute| match attribute {
+                Attribute::Dynamic => "Dynamic".into(),
+                Attribute::External => "External".into(),
+            })
+            .collect();
+        mapping.insert("attributes".into(), attributes.into());
+    }
+    if !class_definition.static_properties.is_empty() {
+        mapping.insert("static_properties".into(), class_definition.static_properties.clone().into());
+    }
+    Yaml::Mapping(mapping)
+}
+
+/// Builds a tagged value, leaving out the fields that are `None`.
+fn tagged(type_name: &str, fields: Vec<(&str, Option<Yaml>)>) -> Yaml {
+    let mut mapping = Mapping::new();
+    mapping.insert(TYPE_KEY.into(), type_name.into());
+    for (name, value) in fields {
+        if let Some(value) = value {
+            mapping.insert(name.into(), value);
+        }
+    }
+    Yaml::Mapping(mapping)
+}
+
+fn non_empty_elements(elements: &[Element], version: AMFVersion) -> Option<Yaml> {
+    (!elements.is_empty()).then(|| elements_to_yaml(elements, version))
+}
+
+fn flag(value: bool) -> Option<Yaml> {
+    value.then_some(Yaml::Bool(true))
+}
+
+fn value_to_yaml(value: &Value, version: AMFVersion) -> Yaml {
+    match value {
+        Value::Number(number) => Yaml::Number(Number::from(*number)),
+        Value::Integer(integer) => Yaml::Number(Number::from(*integer as i64)),
+        Value::Bool(bool) => Yaml::Bool(*bool),
+        Value::String(string) => string.as_str().into(),
+        Value::Null => Yaml::Null,
+        Value::Undefined => tagged("Undefined", Vec::new()),
+        Value::Unsupported => tagged("Unsupported", Vec::new()),
+        Value::Object(elements, class_definition)
+            if *class_definition == value::anonymous_class(version)
+                && has_unique_names(elements)
+                && !elements.iter().any(|element| element.name == TYPE_KEY) =>
+        {
+            elements_to_yaml(elements, version)
+        }
+        Value::Object(elements, class_definition) => tagged(
+            "Object",
+            vec![
+                ("class", class_definition.as_ref().map(class_to_yaml)),
+                ("properties", non_empty_elements(elements, version)),
+            ],
+        ),
+        Value::ECMAArray(dense, elements, length) => tagged(
+            "ECMAArray",
+            vec![
+                ("dense", (!dense.is_empty()).then(|| values_to_yaml(dense, version))),
+                ("properties", non_empty_elements(elements, version)),
+                ("length", Some(Yaml::Number(Number::from(*length as i64)))),
+            ],
+        ),
+        Value::StrictArray(values) => values_to_yaml(values, version),
+        Value::Date(millis, timezone) => {
+            // Dates are written as text when that reads back to exactly the same value
+            let value = match DateTime::from_millis(*millis) {
+                Some(date) if date.to_millis().to_bits() == millis.to_bits() => date.to_string().into(),
+                _ => Yaml::Number(Number::from(*millis)),
+            };
+            tagged(
+                "Date",
+                vec![
+                    ("value", Some(value)),
+                    ("timezone", timezone.map(|timezone| Yaml::Number(Number::from(timezone as i64)))),
+                ],
+            )
+        }
+        Value::XML(content, string) => tagged(
+            "XML",
+            vec![("value", Some(content.as_str().into())), ("string", flag(*string))],
+        ),
+        Value::AMF3(inner) => tagged("AMF3", vec![("value", Some(value_to_yaml(inner, AMFVersion::AMF3)))]),
+        Value::ByteArray(bytes) => {
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            tagged("ByteArray", vec![("hex", Some(hex.into()))])
+        }
+        Value::VectorInt(items, fixed_length) => tagged(
+            "VectorInt",
+            vec![
+                ("values", Some(items.iter().map(|item| Yaml::Number(Number::from(*item as i64))).collect())),
+                ("fixed_length", flag(*fixed_length)),
+            ],
+        ),
+        Value::VectorUInt(items, fixed_length) => tagged(
+            "VectorUInt",
+            vec![
+                ("values", Some(items.iter().map(|item| Yaml::Number(Number::from(*item as i64))).collect())),
+                ("fixed_length", flag(*fixed_length)),
+            ],
+        ),
+        Value::VectorDouble(items, fixed_length) => tagged(
+            "VectorDouble",
+            vec![
+                ("values", Some(items.iter().map(|item| Yaml::Number(Number::from(*item))).collect())),
+                ("fixed_length", flag(*fixed_length)),
+            ],
+        ),
+        Value::VectorObject(values, type_name, fixed_length) => tagged(
+            "VectorObject",
+            vec![
+                ("type_name", Some(type_name.as_str().into())),
+                ("values", Some(values_to_yaml(values, version))),
+                ("fixed_length", flag(*fixed_length)),
+            ],
+        ),
+        Value::Dictionary(pairs, weak_keys) => {
+            let entries = pairs
+                .iter()
+                .map(|(key, value)| {
+                    let mut mapping = Mapping::new();
+                    mapping.insert("key".into(), value_to_yaml(key, version));
+                    mapping.insert("value".into(), value_to_yaml(value, version));
+                    Yaml::Mapping(mapping)
+                })
+                .collect();
+            tagged("Dictionary", vec![("entries", Some(entries)), ("weak_keys", flag(*weak_keys))])
+        }
+        Value::Custom(custom_elements, elements, class_definition) => tagged(
+            "Custom",
+            vec![
+                ("class", class_definition.as_ref().map(class_to_yaml)),
+                ("external", non_empty_elements(custom_elements, version)),
+                ("properties", non_empty_elements(elements, version)),
+            ],
+        ),
+    }
+}
+
+/// Reads the fields of a tagged value, reporting errors at the value at `path`.
+struct Fields<'a> {
+    mapping: &'a Mapping,
+    path: &'a ValuePath,
+    version: AMFVersion,
+}
+
+impl<'a> Fields<'a> {
+    fn error(&self, message: String) -> TextError {
+        schema_error(self.path, message)
+    }
+
+    fn find(&self, name: &str) -> Option<&'a Yaml> {
+        self.mapping.get(&name.into())
+    }
+
+    fn get(&self, name: &str) -> Result<&'a Yaml, TextError> {
+        self.find(name)
+            .ok_or_else(|| self.error(format!("missing field `{}`", name)))
+    }
+
+    fn str(&self, name: &str) -> Result<&'a str, TextError> {
+        self.get(name)?
+            .as_str()
+            .ok_or_else(|| self.error(format!("`{}` must be a string", name)))
+    }
+
+    /// Reads a flag, which is `false` when left out.
+    fn flag(&self, name: &str) -> Result<bool, TextError> {
+        match self.find(name) {
+            Some(yaml) => yaml
+                .as_bool()
+                .ok_or_else(|| self.error(format!("`{}` must be a bool", name))),
+            None => Ok(false),
+        }
+    }
+
+    fn integer<T: std::convert::TryFrom<i64>>(&self, name: &str) -> Result<T, TextError> {
+        integer_from_yaml(self.get(name)?).ok_or_else(|| self.error(format!("`{}` is not a valid integer", name)))
+    }
+
+    /// Reads elements, which are empty when left out.
+    fn elements(&self, name: &str) -> Result<Vec<Element>, TextError> {
+        match self.find(name) {
+            Some(yaml) => elements_from_yaml(yaml, self.path, self.version),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn values(&self, name: &str, version: AMFVersion) -> Result<Vec<Rc<Value>>, TextError> {
+        match self.find(name) {
+            Some(Yaml::Sequence(values)) => values_from_yaml(values, self.path, version),
+            Some(_) => Err(self.error(format!("`{}` must be a sequence", name))),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn items<T>(&self, parse: impl Fn(&Yaml) -> Option<T>) -> Result<Vec<T>, TextError> {
+        match self.get("values")? {
+            Yaml::Sequence(items) => items
+                .iter()
+                .map(|item| parse(item).ok_or_else(|| self.error(format!("invalid vector item {:?}", item))))
+                .collect(),
+            _ => Err(self.error("`values` must be a sequence".to_string())),
+        }
+    }
+
+    /// Reads a class, which is `None` when left out.
+    fn class(&self, name: &str) -> Result<Option<ClassDefinition>, TextError> {
+        let mapping = match self.find(name) {
+            None => return Ok(None),
+            Some(Yaml::String(name)) => return Ok(Some(ClassDefinition::default_with_name(name.clone()))),
+            Some(Yaml::Mapping(mapping)) => mapping,
+            Some(_) => return Err(self.error(format!("`{}` must be a string or a mapping", name))),
+        };
+
+        let class = Fields { mapping, ..*self };
+        let mut class_definition = ClassDefinition::default_with_name(class.str("name")?.to_string());
+
+        if let Some(attributes) = class.find("attributes") {
+            let attributes = attributes
+                .as_sequence()
+                .ok_or_else(|| self.error("`attributes` must be a sequence".to_string()))?;
+            for attribute in attributes {
+                class_definition.attributes.insert(match attribute.as_str() {
+                    Some("Dynamic") => Attribute::Dynamic,
+                    Some("External") => Attribute::External,
+                    _ => return Err(self.error(format!("unknown class attribute {:?}", attribute))),
+                });
+            }
+        }
+
+        if let Some(properties) = class.find("static_properties") {
+            class_definition.static_properties = properties
+                .as_sequence()
+                .and_then(|properties| {
+                    properties
+                        .iter()
+                        .map(|property| property.as_str().map(str::to_string))
+                        .collect()
+                })
+                .ok_or_else(|| self.error("static properties must be strings".to_string()))?;
+        }
+
+        Ok(Some(class_definition))
+    }
+}
+
+fn schema_error(path: &ValuePath, message: impl Into<String>) -> TextError {
+    TextError::Schema {
+        path: path.clone(),
+        message: message.into(),
+    }
+}
+
+fn integer_from_yaml<T: std::convert::TryFrom<i64>>(yaml: &Yaml) -> Option<T> {
+    yaml.as_i64().and_then(|integer| T::try_from(integer).ok())
+}
+
+fn document_from_yaml(yaml: &Yaml) -> Result<Lso, TextError> {
+    let root = ValuePath::default();
+    let mapping = yaml
+        .as_mapping()
+        .ok_or_else(|| schema_error(&root, "expected a mapping"))?;
+    let fields = Fields {
+        mapping,
+        path: &root,
+        version: AMFVersion::AMF0,
+    };
+
+    let format_version = match fields.str("version")? {
+        "AMF0" => AMFVersion::AMF0,
+        "AMF3" => AMFVersion::AMF3,
+        version => return Err(fields.error(format!("unknown version `{}`", version))),
+    };
+
+    Ok(Lso {
+        header: Header {
+            length: 0,
+            name: fields.str("name")?.to_string(),
+            format_version,
+        },
+        body: elements_from_yaml(fields.get("body")?, &root, format_version)?,
+    })
+}
+
+fn element_from_yaml(name: &Yaml, value: &Yaml, path: &ValuePath, version: AMFVersion) -> Result<Element, TextError> {
+    let name = name
+        .as_str()
+        .ok_or_else(|| schema_error(path, format!("property name {:?} must be a string", name)))?;
+    let value = value_from_yaml(value, &path.child(name), version)?;
+
+    Ok(Element::new(name, value))
+}
+
+fn elements_from_yaml(yaml: &Yaml, path: &ValuePath, version: AMFVersion) -> Result<Vec<Element>, TextError> {
+    match yaml {
+        Yaml::Mapping(mapping) => mapping
+            .iter()
+            .map(|(name, value)| element_from_yaml(name, value, path, version))
+            .collect(),
+        Yaml::Sequence(elements) => elements
+            .iter()
+            .map(|element| match element.as_mapping() {
+                Some(mapping) if mapping.len() == 1 => {
+                    let (name, value) = mapping.iter().next().unwrap();
+                    element_from_yaml(name, value, path, version)
+                }
+                _ => Err(schema_error(path, "expected a mapping with a single property")),
+            })
+            .collect(),
+        _ => Err(schema_error(path, "properties must be a mapping or a sequence")),
+    }
+}
+
+fn values_from_yaml(values: &[Yaml], path: &ValuePath, version: AMFVersion) -> Result<Vec<Rc<Value>>, TextError> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| value_from_yaml(value, &path.index(index), version).map(Rc::new))
+        .collect()
+}
+
+fn value_from_yaml(yaml: &Yaml, path: &ValuePath, version: AMFVersion) -> Result<Value, TextError> {
+    let mapping = match yaml {
+        Yaml::Null => return Ok(Value::Null),
+        Yaml::Bool(bool) => return Ok(Value::Bool(*bool)),
+        Yaml::Number(number) if number.is_f64() => return Ok(Value::Number(number.as_f64().unwrap())),
+        Yaml::Number(_) => {
+            return Ok(match (version, integer_from_yaml(yaml)) {
+                (AMFVersion::AMF3, Some(integer)) => Value::Integer(integer),
+                // Too large for an integer, or typed into an AMF0 document
+                _ => Value::Number(yaml.as_f64().unwrap()),
+            })
+        }
+        Yaml::String(string) => return Ok(Value::String(string.clone())),
+        Yaml::Sequence(values) => return Ok(Value::StrictArray(values_from_yaml(values, path, version)?)),
+        Yaml::Mapping(mapping) if !mapping.contains_key(&TYPE_KEY.into()) => {
+            return Ok(Value::Object(
+                elements_from_yaml(yaml, path, version)?,
+                value::anonymous_class(version),
+            ))
+        }
+        Yaml::Mapping(mapping) => mapping,
+    };
+
+    let fields = Fields { mapping, path, version };
+
+    let value = match fields.str(TYPE_KEY)? {
+        "Null" => Value::Null,
+        "Undefined" => Value::Undefined,
+        "Unsupported" => Value::Unsupported,
+        "Object" => Value::Object(fields.elements("properties")?, fields.class("class")?),
+        "ECMAArray" => Value::ECMAArray(
+            fields.values("dense", version)?,
+            fields.elements("properties")?,
+            fields.integer("length")?,
+        ),
+        "Date" => {
+            let millis = match fields.get("value")? {
+                Yaml::String(date) => date
+                    .parse::<DateTime>()
+                    .map_err(|error| fields.error(error.to_string()))?
+                    .to_millis(),
+                value => value
+                    .as_f64()
+                    .ok_or_else(|| fields.error("`value` must be a date or a number".to_string()))?,
+            };
+            let timezone = match fields.find("timezone") {
+                Some(_) => Some(fields.integer("timezone")?),
+                None => None,
+            };
+            Value::Date(millis, timezone)
+        }
+        "XML" => Value::XML(fields.str("value")?.to_string(), fields.flag("string")?),
+        "AMF3" => Value::AMF3(Rc::new(value_from_yaml(fields.get("value")?, path, AMFVersion::AMF3)?)),
+        "ByteArray" => {
+            let hex = fields.str("hex")?;
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| fields.error("`hex` must be pairs of hex digits".to_string()))?;
+            Value::ByteArray(bytes)
+        }
+        "VectorInt" => Value::VectorInt(fields.items(integer_from_yaml)?, fields.flag("fixed_length")?),
+        "VectorUInt" => Value::VectorUInt(fields.items(integer_from_yaml)?, fields.flag("fixed_length")?),
+        "VectorDouble" => Value::VectorDouble(fields.items(Yaml::as_f64)?, fields.flag("fixed_length")?),
+        "VectorObject" => Value::VectorObject(
+            fields.values("values", version)?,
+            fields.str("type_name")?.to_string(),
+            fields.flag("fixed_length")?,
+        ),
+        "Dictionary" => {
+            let entries = match fields.get("entries")? {
+                Yaml::Sequence(entries) => entries,
+                _ => return Err(fields.error("`entries` must be a sequence".to_string())),
+            };
+            let pairs = entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| {
+                    let entry_path = path.index(index);
+                    let entry = Fields {
+                        mapping: entry
+                            .as_mapping()
+                            .ok_or_else(|| schema_error(&entry_path, "expected a mapping"))?,
+                        path: &entry_path,
+                        version,
+                    };
+                    let key = value_from_yaml(entry.get("key")?, &entry_path.child("<key>"), version)?;
+                    let value = value_from_yaml(entry.get("value")?, &entry_path, version)?;
+
+                    Ok((Rc::new(key), Rc::new(value)))
+                })
+                .collect::<Result<_, TextError>>()?;
+            Value::Dictionary(pairs, fields.flag("weak_keys")?)
+        }
+        "Custom" => Value::Custom(
+            fields.elements("external")?,
+            fields.elements("properties")?,
+            fields.class("class")?,
+        ),
+        type_name => return Err(fields.error(format!("unknown type `{}`", type_name))),
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(name: &str, attributes: Vec<Attribute>, static_properties: &[&str]) -> Option<ClassDefinition> {
+        Some(ClassDefinition {
+            name: name.to_string(),
+            attributes: attributes.into_iter().collect(),
+            static_properties: static_properties.iter().map(|name| name.to_string()).collect(),
+        })
+    }
+
+    fn amf3_document() -> Lso {
+        let values = |values: Vec<Value>| values.into_iter().map(Rc::new).collect::<Vec<_>>();
+
+        Lso::new(
+            vec![
+                Element::new("number", Value::Number(0.5)),
+                Element::new("whole_number", Value::Number(3.0)),
+                Element::new("nan", Value::Number(f64::NAN)),
+                Element::new("integer", Value::Integer(-7)),
+                Element::new("bool", Value::Bool(true)),
+                Element::new("string", Value::String("text: with \"quotes\"".to_string())),
+                Element::new("number_string", Value::String("12".to_string())),
+                Element::new("null", Value::Null),
+                Element::new("undefined", Value::Undefined),
+                Element::new(
+                    "object",
+                    Value::Object(vec![Element::new("x", Value::Integer(1))], value::anonymous_class(AMFVersion::AMF3)),
+                ),
+                Element::new(
+                    "typed",
+                    Value::Object(
+                        vec![Element::new("x", Value::Number(1.5)), Element::new("x", Value::Null)],
+                        class("Point", vec![Attribute::Dynamic], &["x"]),
+                    ),
+                ),
+                Element::new(
+                    "ecma_array",
+                    Value::ECMAArray(values(vec![Value::Bool(false)]), vec![Element::new("key", Value::Null)], 2),
+                ),
+                Element::new("array", Value::StrictArray(values(vec![Value::Integer(1), Value::String("two".to_string())]))),
+                Element::new("date", Value::Date(1_634_564_220_000.0, None)),
+                Element::new("invalid_date", Value::Date(f64::NAN, None)),
+                Element::new("xml", Value::XML("<a/>".to_string(), false)),
+                Element::new("bytes", Value::ByteArray(vec![0, 15, 255])),
+                Element::new("ints", Value::VectorInt(vec![-1, 2], true)),
+                Element::new("uints", Value::VectorUInt(vec![u32::MAX], false)),
+                Element::new("doubles", Value::VectorDouble(vec![0.25, f64::INFINITY], false)),
+                Element::new("objects", Value::VectorObject(values(vec![Value::Null]), "Item".to_string(), false)),
+                Element::new(
+                    "dictionary",
+                    Value::Dictionary(vec![(Rc::new(Value::String("key".to_string())), Rc::new(Value::Integer(1)))], true),
+                ),
+            ],
+            "save game",
+            AMFVersion::AMF3,
+        )
+    }
+
+    fn amf0_document() -> Lso {
+        Lso::new(
+            vec![
+                Element::new("number", Value::Number(2.0)),
+                Element::new("object", Value::Object(vec![Element::new("a", Value::Bool(false))], None)),
+                Element::new("date", Value::Date(0.0, Some(60))),
+                Element::new("unsupported", Value::Unsupported),
+                Element::new(
+                    "wrapped",
+                    Value::AMF3(Rc::new(Value::Object(
+                        vec![Element::new("count", Value::Integer(5))],
+                        value::anonymous_class(AMFVersion::AMF3),
+                    ))),
+                ),
+            ],
+            "amf0",
+            AMFVersion::AMF0,
+        )
+    }
+
+    fn assert_same(read: &Lso, lso: &Lso) {
+        assert_eq!(read.header.name, lso.header.name);
+        assert_eq!(read.header.format_version, lso.header.format_version);
+        // NaN isn't equal to itself, but its debug output is
+        assert_eq!(format!("{:?}", read.body), format!("{:?}", lso.body));
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        for lso in &[amf3_document(), amf0_document()] {
+            for &format in &Format::ALL {
+                let text = format.write(lso);
+                let read = format.read(&text).unwrap_or_else(|error| panic!("{}: {}\n{}", format.name(), error, text));
+                assert_same(&read, lso);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_the_documented_example() {
+        let lso = from_yaml(
+            "name: savegame\nversion: AMF3\nbody:\n  score: 1200\n  player:\n    name: Alice\n    health: 0.75\n  unlocked: [1, 2, 3]\n",
+        )
+        .unwrap();
+
+        assert_eq!(lso.header.name, "savegame");
+        assert_eq!(lso.body[0], Element::new("score", Value::Integer(1200)));
+        assert_eq!(
+            ValuePath::default().child("player").child("health").get(&lso.body),
+            Some(&Value::Number(0.75))
+        );
+    }
+
+    #[test]
+    fn integers_become_numbers_in_amf0() {
+        let lso = from_yaml("name: t\nversion: AMF0\nbody:\n  a: 1\n").unwrap();
+
+        assert_eq!(lso.body[0], Element::new("a", Value::Number(1.0)));
+    }
+
+    #[test]
+    fn reports_where_text_is_invalid() {
+        let error = from_yaml("name: t\nversion: AMF3\nbody:\n  a: {$type: Date, value: yesterday}\n").unwrap_err();
+
+        assert!(matches!(error, TextError::Schema { path, .. } if path.to_string() == "a"));
+        assert!(from_yaml("name: t\nversion: AMF4\nbody: {}\n").is_err());
+        assert!(from_toml("name = 1").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use flash_lso::types::{AMFVersion, Attribute, ClassDefinition, Value};
+
+use crate::date::DateTime;
+
+/// Smallest value an AMF3 integer can hold, integers are encoded as 29 bit signed values.
+pub const INTEGER_MIN: i32 = -(1 << 28);
+/// Largest value an AMF3 integer can hold.
+pub const INTEGER_MAX: i32 = (1 << 28) - 1;
+
+/// The name of the type of `value`, as shown to the user.
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Number(_) => "Number",
+        Value::Bool(_) => "Bool",
+        Value::String(_) => "String",
+        Value::Object(_, _) => "Object",
+        Value::Null => "Null",
+        Value::Undefined => "Undefined",
+        Value::ECMAArray(_, _, _) => "ECMAArray",
+        Value::StrictArray(_) => "StrictArray",
+        Value::Date(_, _) => "Date",
+        Value::Unsupported => "Unsupported",
+        Value::XML(_, _) => "XML",
+        Value::AMF3(_) => "AMF3",
+        Value::Integer(_) => "Integer",
+        Value::ByteArray(_) => "ByteArray",
+        Value::VectorInt(_, _) => "VectorInt",
+        Value::VectorUInt(_, _) => "VectorUInt",
+        Value::VectorDouble(_, _) => "VectorDouble",
+        Value::VectorObject(_, _, _) => "VectorObject",
+        Value::Dictionary(_, _) => "Dictionary",
+        Value::Custom(_, _, _) => "Custom",
+    }
+}
+
+/// The types new values can be created as, in the order they are offered.
+pub const NEW_TYPES: [&str; 17] = [
+    "Null",
+    "Undefined",
+    "Bool",
+    "Number",
+    "Integer",
+    "String",
+    "Date",
+    "XML",
+    "Object",
+    "ECMAArray",
+    "StrictArray",
+    "ByteArray",
+    "VectorInt",
+    "VectorUInt",
+    "VectorDouble",
+    "VectorObject",
+    "Dictionary",
+];
+
+/// The types of `NEW_TYPES` a document of `version` can hold, see `can_hold`.
+pub fn new_types(version: AMFVersion) -> impl Iterator<Item = &'static str> {
+    NEW_TYPES.iter().copied().filter(move |type_name| {
+        default_value(type_name, version).map_or(false, |value| can_hold(version, &value))
+    })
+}
+
+/// An empty or zero value of the type called `type_name`, see `NEW_TYPES`, with the classes of a
+/// document of `version`. Documents can't hold every type, see `new_types`.
+pub fn default_value(type_name: &str, version: AMFVersion) -> Option<Value> {
+    let value = match type_name {
+        "Null" => Value::Null,
+        "Undefined" => Value::Undefined,
+        "Bool" => Value::Bool(false),
+        "Number" => Value::Number(0.0),
+        "Integer" => Value::Integer(0),
+        "String" => Value::String(String::new()),
+        "Date" => Value::Date(0.0, None),
+        "XML" => Value::XML(String::new(), true),
+        "Object" => Value::Object(Vec::new(), anonymous_class(version)),
+        "ECMAArray" => Value::ECMAArray(Vec::new(), Vec::new(), 0),
+        "StrictArray" => Value::StrictArray(Vec::new()),
+        "ByteArray" => Value::ByteArray(Vec::new()),
+        "VectorInt" => Value::VectorInt(Vec::new(), false),
+        "VectorUInt" => Value::VectorUInt(Vec::new(), false),
+        "VectorDouble" => Value::VectorDouble(Vec::new(), false),
+        "VectorObject" => Value::VectorObject(Vec::new(), "Object".to_string(), false),
+        "Dictionary" => Value::Dictionary(Vec::new(), false),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// `value` as a document of `version` holds it, AMF0 has no integers so they become numbers.
+/// `None` for the other values only AMF3 can encode, see `can_hold`.
+pub fn for_version(value: Value, version: AMFVersion) -> Option<Value> {
+    match (version, value) {
+        (AMFVersion::AMF0, Value::Integer(integer)) => Some(Value::Number(f64::from(integer))),
+        (version, value) if can_hold(version, &value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether a document of `version` can hold `value` among its own values, rather than inside a
+/// value stored as AMF3. AMF0 readers only read objects after the switch to AMF3, so AMF0
+/// documents can't hold other values only AMF3 can encode.
+pub fn can_hold(version: AMFVersion, value: &Value) -> bool {
+    match (version, value) {
+        (AMFVersion::AMF3, _) => true,
+        (AMFVersion::AMF0, Value::AMF3(inner)) => matches!(**inner, Value::Object(_, _) | Value::Custom(_, _, _)),
+        (AMFVersion::AMF0, value) => !matches!(
+            value,
+            Value::Integer(_)
+                | Value::ByteArray(_)
+                | Value::VectorInt(_, _)
+                | Value::VectorUInt(_, _)
+                | Value::VectorDouble(_, _)
+                | Value::VectorObject(_, _, _)
+                | Value::Dictionary(_, _)
+                | Value::Custom(_, _, _)
+        ),
+    }
+}
+
+/// The class of objects without one, AMF3 objects always have a class and it has no name.
+pub fn anonymous_class(version: AMFVersion) -> Option<ClassDefinition> {
+    match version {
+        AMFVersion::AMF0 => None,
+        AMFVersion::AMF3 => Some(ClassDefinition {
+            name: String::new(),
+            attributes: Attribute::Dynamic.into(),
+            static_properties: Vec::new(),
+        }),
+    }
+}
+
+/// Calls `f` with `value` and every value nested inside it.
+pub fn walk<F: FnMut(&Value)>(value: &Value, f: &mut F) {
+    f(value);
+
+    match value {
+        Value::Object(elements, _) => {
+            for element in elements {
+                walk(element.value(), f);
+            }
+        }
+        Value::ECMAArray(values, elements, _) => {
+            for value in values {
+                walk(value, f);
+            }
+            for element in elements {
+                walk(element.value(), f);
+            }
+        }
+        Value::StrictArray(values) | Value::VectorObject(values, _, _) => {
+            for value in values {
+                walk(value, f);
+            }
+        }
+        Value::AMF3(value) => walk(value, f),
+        Value::Dictionary(pairs, _) => {
+            for (key, value) in pairs {
+                walk(key, f);
+                walk(value, f);
+            }
+        }
+        Value::Custom(custom_elements, elements, _) => {
+            for element in custom_elements.iter().chain(elements) {
+                walk(element.value(), f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The type of `value` followed by its contents or size.
+pub fn describe(value: &Value) -> String {
+    let type_name = type_name(value);
+
+    match value {
+        Value::Object(elements, Some(class_definition)) if !class_definition.name.is_empty() => {
+            format!("{} {} {{{}}}", type_name, class_definition.name, elements.len())
+        }
+        Value::Object(elements, _) => format!("{} {{{}}}", type_name, elements.len()),
+        Value::ECMAArray(dense, elements, length) => format!(
+            "{} [{}] {{{}}} (length {})",
+            type_name,
+            dense.len(),
+            elements.len(),
+            length
+        ),
+        Value::StrictArray(values) | Value::VectorObject(values, _, _) => {
+            format!("{} [{}]", type_name, values.len())
+        }
+        Value::VectorInt(items, _) => format!("{} [{}]", type_name, items.len()),
+        Value::VectorUInt(items, _) => format!("{} [{}]", type_name, items.len()),
+        Value::VectorDouble(items, _) => format!("{} [{}]", type_name, items.len()),
+        Value::Dictionary(pairs, _) => format!("{} [{}]", type_name, pairs.len()),
+        Value::ByteArray(bytes) => format!("{} ({} bytes)", type_name, bytes.len()),
+        Value::Custom(_, _, class_definition) => format!(
+            "{} {}",
+            type_name,
+            class_definition.as_ref().map_or("", |class_definition| &class_definition.name)
+        ),
+        Value::AMF3(inner) => format!("{} {}", type_name, describe(inner)),
+        Value::String(string) | Value::XML(string, _) => format!("{} {:?}", type_name, string),
+        Value::Null | Value::Undefined | Value::Unsupported => type_name.to_string(),
+        value => format!("{} {}", type_name, format_scalar(value).unwrap_or_default()),
+    }
+}
+
+/// The plain text of values without children, as printed and read by the command line.
+pub fn format_scalar(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Bool(bool) => bool.to_string(),
+        Value::String(string) | Value::XML(string, _) => string.clone(),
+        Value::Null => "null".to_string(),
+        Value::Undefined => "undefined".to_string(),
+        Value::Unsupported => "unsupported".to_string(),
+        Value::Date(millis, _) => match DateTime::from_millis(*millis) {
+            Some(date_time) => date_time.to_string(),
+            None => millis.to_string(),
+        },
+        Value::ByteArray(bytes) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        Value::AMF3(inner) => return format_scalar(inner),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    #[test]
+    fn amf0_documents_only_hold_amf3_objects() {
+        let object = Value::Object(Vec::new(), anonymous_class(AMFVersion::AMF3));
+
+        assert!(can_hold(AMFVersion::AMF0, &Value::AMF3(Rc::new(object))));
+        assert!(can_hold(AMFVersion::AMF0, &Value::Number(1.0)));
+        assert!(!can_hold(AMFVersion::AMF0, &Value::Integer(1)));
+        assert!(!can_hold(AMFVersion::AMF0, &Value::AMF3(Rc::new(Value::Integer(1)))));
+        assert!(can_hold(AMFVersion::AMF3, &Value::Integer(1)));
+    }
+
+    #[test]
+    fn amf0_documents_hold_integers_as_numbers() {
+        assert_eq!(for_version(Value::Integer(3), AMFVersion::AMF0), Some(Value::Number(3.0)));
+        assert_eq!(for_version(Value::Integer(3), AMFVersion::AMF3), Some(Value::Integer(3)));
+        assert_eq!(for_version(Value::ByteArray(Vec::new()), AMFVersion::AMF0), None);
+        assert_eq!(for_version(Value::AMF3(Rc::new(Value::Integer(3))), AMFVersion::AMF0), None);
+        assert_eq!(for_version(Value::Bool(true), AMFVersion::AMF0), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn new_types_depend_on_the_version() {
+        let amf0: Vec<_> = new_types(AMFVersion::AMF0).collect();
+        assert_eq!(
+            amf0,
+            ["Null", "Undefined", "Bool", "Number", "String", "Date", "XML", "Object", "ECMAArray", "StrictArray"]
+        );
+        assert_eq!(new_types(AMFVersion::AMF3).collect::<Vec<_>>(), NEW_TYPES);
+    }
+}